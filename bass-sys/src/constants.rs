@@ -30,6 +30,46 @@ pub const BASS_DEVICE_TYPE_SPDIF: DWORD = 0x09000000;
 pub const BASS_DEVICE_TYPE_HDMI: DWORD = 0x0a000000;
 pub const BASS_DEVICE_TYPE_DISPLAYPORT: DWORD = 0x40000000;
 
+pub const BASS_OK: c_int = 0;
+pub const BASS_ERROR_MEM: c_int = 1;
+pub const BASS_ERROR_FILEOPEN: c_int = 2;
+pub const BASS_ERROR_DRIVER: c_int = 3;
+pub const BASS_ERROR_BUFLOST: c_int = 4;
+pub const BASS_ERROR_HANDLE: c_int = 5;
+pub const BASS_ERROR_FORMAT: c_int = 6;
+pub const BASS_ERROR_POSITION: c_int = 7;
+pub const BASS_ERROR_INIT: c_int = 8;
+pub const BASS_ERROR_START: c_int = 9;
+pub const BASS_ERROR_SSL: c_int = 10;
+pub const BASS_ERROR_ALREADY: c_int = 14;
+pub const BASS_ERROR_NOTAUDIO: c_int = 17;
+pub const BASS_ERROR_NOCHAN: c_int = 18;
+pub const BASS_ERROR_ILLTYPE: c_int = 19;
+pub const BASS_ERROR_ILLPARAM: c_int = 20;
+pub const BASS_ERROR_NO3D: c_int = 21;
+pub const BASS_ERROR_NOEAX: c_int = 22;
+pub const BASS_ERROR_DEVICE: c_int = 23;
+pub const BASS_ERROR_NOPLAY: c_int = 24;
+pub const BASS_ERROR_FREQ: c_int = 25;
+pub const BASS_ERROR_NOTFILE: c_int = 27;
+pub const BASS_ERROR_NOHW: c_int = 29;
+pub const BASS_ERROR_EMPTY: c_int = 31;
+pub const BASS_ERROR_NONET: c_int = 32;
+pub const BASS_ERROR_CREATE: c_int = 33;
+pub const BASS_ERROR_NOFX: c_int = 34;
+pub const BASS_ERROR_NOTAVAIL: c_int = 37;
+pub const BASS_ERROR_DECODE: c_int = 38;
+pub const BASS_ERROR_DX: c_int = 39;
+pub const BASS_ERROR_TIMEOUT: c_int = 40;
+pub const BASS_ERROR_FILEFORM: c_int = 41;
+pub const BASS_ERROR_SPEAKER: c_int = 42;
+pub const BASS_ERROR_VERSION: c_int = 43;
+pub const BASS_ERROR_CODEC: c_int = 44;
+pub const BASS_ERROR_ENDED: c_int = 45;
+pub const BASS_ERROR_BUSY: c_int = 46;
+pub const BASS_ERROR_UNSTREAMABLE: c_int = 47;
+pub const BASS_ERROR_UNKNOWN: c_int = -1;
+
 pub const BASS_SAMPLE_8BITS: DWORD = 1;
 pub const BASS_SAMPLE_FLOAT: DWORD = 256;
 pub const BASS_SAMPLE_MONO: DWORD = 2;
@@ -43,6 +83,34 @@ pub const BASS_SAMPLE_OVER_VOL: DWORD = 0x10000;
 pub const BASS_SAMPLE_OVER_POS: DWORD = 0x20000;
 pub const BASS_SAMPLE_OVER_DIST: DWORD = 0x30000;
 
+pub const BASS_STREAM_PRESCAN: DWORD = 0x20000;
+pub const BASS_STREAM_AUTOFREE: DWORD = 0x40000;
+pub const BASS_STREAM_DECODE: DWORD = 0x200000;
+
+pub const BASS_UNICODE: DWORD = 0x80000000;
+
+pub const BASS_STREAMPROC_END: DWORD = 0x80000000;
+
+pub const BASS_ACTIVE_STOPPED: DWORD = 0;
+pub const BASS_ACTIVE_PLAYING: DWORD = 1;
+pub const BASS_ACTIVE_STALLED: DWORD = 2;
+pub const BASS_ACTIVE_PAUSED: DWORD = 3;
+pub const BASS_ACTIVE_PAUSED_DEVICE: DWORD = 4;
+
+pub const BASS_DATA_AVAILABLE: DWORD = 0;
+pub const BASS_DATA_FIXED: DWORD = 0x20000000;
+pub const BASS_DATA_FLOAT: DWORD = 0x40000000;
+pub const BASS_DATA_FFT256: DWORD = 0x80000000;
+pub const BASS_DATA_FFT512: DWORD = 0x80000001;
+pub const BASS_DATA_FFT1024: DWORD = 0x80000002;
+pub const BASS_DATA_FFT2048: DWORD = 0x80000003;
+pub const BASS_DATA_FFT4096: DWORD = 0x80000004;
+pub const BASS_DATA_FFT8192: DWORD = 0x80000005;
+pub const BASS_DATA_FFT16384: DWORD = 0x80000006;
+pub const BASS_DATA_FFT_INDIVIDUAL: DWORD = 0x10;
+pub const BASS_DATA_FFT_NOWINDOW: DWORD = 0x20;
+pub const BASS_DATA_FFT_REMOVEDC: DWORD = 0x40;
+
 pub const BASS_POS_BYTE: DWORD = 0;
 pub const BASS_POS_MUSIC_ORDER: DWORD = 1;
 pub const BASS_POS_OGG: DWORD = 3;
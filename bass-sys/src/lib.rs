@@ -12,14 +12,17 @@ extern_log! {
     pub fn BASS_GetConfig(option: DWORD) -> DWORD;
 
     pub fn BASS_ChannelGetDevice(handle: DWORD) -> DWORD;
-    pub fn BASS_ChannelGetAttribute(handle:DWORD, attrib:DWORD, value:*mut c_float);
-    pub fn BASS_ChannelSetAttribute(handle:DWORD, attrib:DWORD, value:c_float);
+    pub fn BASS_ChannelGetAttribute(handle:DWORD, attrib:DWORD, value:*mut c_float) -> BOOL;
+    pub fn BASS_ChannelSetAttribute(handle:DWORD, attrib:DWORD, value:c_float) -> BOOL;
     pub fn BASS_ChannelSetDevice(handle: DWORD, device: DWORD) -> BOOL;
     pub fn BASS_ChannelPlay(handle: DWORD, restart: BOOL) -> BOOL;
     pub fn BASS_ChannelPause(handle: DWORD) -> BOOL;
     pub fn BASS_ChannelGetLength(handle: DWORD, mode: DWORD) -> QWORD;
     pub fn BASS_ChannelGetPosition(handle: DWORD, mode: DWORD) -> QWORD;
-    pub fn BASS_ChannelSetPosition(handle: DWORD, pos: QWORD, mode: DWORD);
+    pub fn BASS_ChannelSetPosition(handle: DWORD, pos: QWORD, mode: DWORD) -> BOOL;
+    pub fn BASS_ChannelIsActive(handle: DWORD) -> DWORD;
+    pub fn BASS_ChannelGetInfo(handle: DWORD, info: *mut BASS_CHANNELINFO) -> BOOL;
+    pub fn BASS_ChannelGetData(handle: DWORD, buffer: *mut c_void, length: DWORD) -> DWORD;
     pub fn BASS_ChannelBytes2Seconds(handle: DWORD, pos: QWORD) -> c_double;
     pub fn BASS_ChannelSeconds2Bytes(handle: DWORD, pos: c_double) -> QWORD;
 
@@ -47,5 +50,15 @@ extern_log! {
     ) -> HSTREAM;
     pub fn BASS_StreamFree(handle: HSTREAM) -> BOOL;
 
+    pub fn BASS_SampleLoad(
+        mem: BOOL,
+        file: *const c_void,
+        offset: QWORD,
+        length: DWORD,
+        max: DWORD,
+        flags: DWORD,
+    ) -> HSAMPLE;
+    pub fn BASS_SampleFree(handle: HSAMPLE) -> BOOL;
+    pub fn BASS_SampleGetChannel(handle: HSAMPLE, onlynew: BOOL) -> HCHANNEL;
     pub fn BASS_SampleGetInfo(handle: HSAMPLE, info: *mut BASS_SAMPLE) -> BOOL;
 }
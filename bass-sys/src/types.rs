@@ -4,6 +4,8 @@ pub type DWORD = c_uint;
 pub type QWORD = c_ulong;
 pub type BOOL = c_int;
 
+pub type HCHANNEL = DWORD;
+pub type HPLUGIN = DWORD;
 pub type HSAMPLE = DWORD;
 pub type HSTREAM = DWORD;
 
@@ -16,6 +18,19 @@ pub struct BASS_DEVICEINFO {
     pub flags: DWORD,
 }
 
+#[repr(C)]
+pub struct BASS_CHANNELINFO {
+    /// Default playback rate.
+    pub freq: DWORD,
+    pub chans: DWORD,
+    pub flags: DWORD,
+    pub ctype: DWORD,
+    pub origres: DWORD,
+    pub plugin: HPLUGIN,
+    pub sample: HSAMPLE,
+    pub filename: *const c_char,
+}
+
 #[repr(C)]
 pub struct BASS_SAMPLE {
     /// Default sample rate.
@@ -0,0 +1,500 @@
+mod spectrum;
+mod stretch;
+mod tempo;
+mod waveform;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use bass::constants::*;
+
+use self::stretch::StretchedStream;
+
+pub use self::spectrum::{FrequencyScale, Spectrogram, BANDS, FFT_SIZES};
+pub use self::tempo::TempoEstimate;
+pub use self::waveform::{Peak, Waveform};
+
+/// How many times a single sample can be playing at once. When this is exceeded, the playback
+/// that's furthest along gets cut off.
+pub const MAX_SAMPLE_PLAYBACKS: u32 = 16;
+
+pub struct AudioEngine {
+    // samples have to be freed before the device is
+    samples: HashMap<PathBuf, Sample>,
+    current_device: AudioDevice,
+}
+
+impl AudioEngine {
+    pub fn new() -> Result<Self> {
+        let current_device = AudioDevice::init_default()?;
+        Ok(AudioEngine {
+            samples: HashMap::new(),
+            current_device,
+        })
+    }
+
+    /// Gets the sample loaded from the given file, loading it if it hasn't been used before.
+    pub fn sample(&mut self, path: impl AsRef<Path>) -> Result<&Sample> {
+        let path = path.as_ref();
+        if !self.samples.contains_key(path) {
+            let sample = Sample::load(path)?;
+            self.samples.insert(path.to_path_buf(), sample);
+        }
+        Ok(&self.samples[path])
+    }
+
+    /// Unloads all cached samples.
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Plays the sample from the given file on a new channel, so it can overlap with itself.
+    ///
+    /// Volume goes from 0 to 1, and pan goes from -1 (left) to 1 (right).
+    pub fn play_sample(&mut self, path: impl AsRef<Path>, volume: f32, pan: f32) -> Result<()> {
+        let sample = self.sample(path)?;
+        let channel = check_handle(unsafe { bass::BASS_SampleGetChannel(sample.handle(), 0) })?;
+        unsafe {
+            check(bass::BASS_ChannelSetAttribute(
+                channel,
+                BASS_ATTRIB_VOL,
+                volume,
+            ))?;
+            check(bass::BASS_ChannelSetAttribute(
+                channel,
+                BASS_ATTRIB_PAN,
+                pan,
+            ))?;
+            check(bass::BASS_ChannelPlay(channel, 0))?;
+        }
+        Ok(())
+    }
+
+    pub fn play(&self, sound: &Sound) -> Result<()> {
+        let handle = sound.handle();
+        check(unsafe { bass::BASS_ChannelPlay(handle, 0) })?;
+        Ok(())
+    }
+
+    /// Plays the sound from the beginning, even if it's already playing.
+    pub fn play_from_start(&self, sound: &Sound) -> Result<()> {
+        let handle = sound.handle();
+        check(unsafe { bass::BASS_ChannelPlay(handle, 1) })?;
+        Ok(())
+    }
+
+    pub fn pause(&self, sound: &Sound) -> Result<()> {
+        let handle = sound.handle();
+        match check(unsafe { bass::BASS_ChannelPause(handle) }) {
+            // the sound already stopped by itself, for example because it reached the end
+            Ok(()) | Err(BassError::NoPlay) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+pub struct AudioDevice {
+    id: i32,
+}
+
+impl AudioDevice {
+    pub fn init_default() -> Result<Self> {
+        Self::init(-1)
+    }
+
+    pub fn init(id: i32) -> Result<Self> {
+        check(unsafe { bass::BASS_Init(id, 44100, 0, ptr::null(), ptr::null()) })
+            .context("failed to initialize audio device")?;
+        Ok(AudioDevice { id })
+    }
+}
+
+impl Drop for AudioDevice {
+    fn drop(&mut self) {
+        unsafe { bass::BASS_Free() };
+    }
+}
+
+pub struct Sound {
+    path: PathBuf,
+    handle: u32,
+    /// Used instead of the normal stream when the playback rate isn't 1.
+    stretched: Option<StretchedStream>,
+    /// Filled in once the waveform has been decoded, if it was asked for.
+    waveform: Option<Arc<Mutex<Option<Arc<Waveform>>>>>,
+}
+
+impl Sound {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::create_with_flags(path, 0)
+    }
+
+    /// Creates a sound that starts over from the beginning when it reaches the end.
+    pub fn create_looping(path: impl AsRef<Path>) -> Result<Self> {
+        Self::create_with_flags(path, BASS_SAMPLE_LOOP)
+    }
+
+    fn create_with_flags(path: impl AsRef<Path>, flags: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let (path_buf, path_flags) = bass_path(path)?;
+        let handle = unsafe {
+            bass::BASS_StreamCreateFile(0, path_buf.as_ptr() as *mut _, 0, 0, flags | path_flags)
+        };
+        let handle = check_handle(handle).with_context(|| format!("failed to load {:?}", path))?;
+
+        Ok(Sound {
+            path: path.to_path_buf(),
+            handle,
+            stretched: None,
+            waveform: None,
+        })
+    }
+
+    /// Starts decoding the waveform of the sound in the background.
+    pub fn decode_waveform(&mut self) {
+        if self.waveform.is_none() {
+            self.waveform = Some(Waveform::decode_in_background(self.path.clone()));
+        }
+    }
+
+    /// The waveform of the sound, if it's done decoding.
+    pub fn waveform(&self) -> Option<Arc<Waveform>> {
+        self.waveform.as_ref()?.lock().unwrap().clone()
+    }
+
+    /// Starts analyzing the frequencies of the sound over time in the background.
+    pub fn analyze_spectrogram(
+        &self,
+        fft_size: usize,
+        scale: FrequencyScale,
+    ) -> Arc<Mutex<Option<Result<Arc<Spectrogram>, String>>>> {
+        Spectrogram::analyze_in_background(self.path.clone(), fft_size, scale)
+    }
+
+    /// Starts guessing the timing of the part of the sound between two times (in seconds) in the
+    /// background.
+    pub fn detect_tempo(
+        &self,
+        start: f64,
+        end: f64,
+    ) -> Arc<Mutex<Option<Result<TempoEstimate, String>>>> {
+        TempoEstimate::detect_in_background(self.path.clone(), start, end)
+    }
+
+    /// The handle of the channel that's actually being played.
+    pub fn handle(&self) -> u32 {
+        match &self.stretched {
+            Some(stretched) => stretched.handle(),
+            None => self.handle,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        unsafe { bass::BASS_ChannelIsActive(self.handle()) == BASS_ACTIVE_PLAYING }
+    }
+
+    pub fn length(&self) -> Result<f64> {
+        let bytes = unsafe { bass::BASS_ChannelGetLength(self.handle, BASS_POS_BYTE) };
+        if bytes == !0 {
+            return Err(BassError::last().into());
+        }
+        bytes_to_seconds(self.handle, bytes)
+    }
+
+    pub fn position(&self) -> Result<f64> {
+        match &self.stretched {
+            Some(stretched) => stretched.position(),
+            None => channel_position(self.handle),
+        }
+    }
+
+    pub fn set_position(&self, pos: f64) -> Result<()> {
+        match &self.stretched {
+            Some(stretched) => stretched.set_position(pos),
+            None => set_channel_position(self.handle, pos),
+        }
+    }
+
+    pub fn playback_rate(&self) -> f64 {
+        match &self.stretched {
+            Some(stretched) => stretched.rate(),
+            None => 1.0,
+        }
+    }
+
+    /// Changes how fast the sound plays, without changing its pitch. The position and whether
+    /// it's playing stay the same.
+    pub fn set_playback_rate(&mut self, rate: f64) -> Result<()> {
+        if (rate - self.playback_rate()).abs() < f64::EPSILON {
+            return Ok(());
+        }
+
+        let pos = self.position()?;
+        if (rate - 1.0).abs() < f64::EPSILON {
+            let was_playing = self.is_playing();
+            self.stretched = None;
+            set_channel_position(self.handle, pos)?;
+            if was_playing {
+                check(unsafe { bass::BASS_ChannelPlay(self.handle, 0) })?;
+            }
+            return Ok(());
+        }
+
+        match &self.stretched {
+            Some(stretched) => stretched.set_rate(rate)?,
+            None => {
+                let was_playing = self.is_playing();
+                let mut volume = 1.0;
+                check(unsafe {
+                    bass::BASS_ChannelGetAttribute(self.handle, BASS_ATTRIB_VOL, &mut volume)
+                })?;
+
+                let stretched = StretchedStream::create(&self.path, rate)?;
+                stretched.set_position(pos)?;
+                check(unsafe {
+                    bass::BASS_ChannelSetAttribute(stretched.handle(), BASS_ATTRIB_VOL, volume)
+                })?;
+
+                if was_playing {
+                    check(unsafe { bass::BASS_ChannelPause(self.handle) })?;
+                    check(unsafe { bass::BASS_ChannelPlay(stretched.handle(), 0) })?;
+                }
+                self.stretched = Some(stretched);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_volume(&self, volume: f32) -> Result<()> {
+        check(unsafe { bass::BASS_ChannelSetAttribute(self.handle, BASS_ATTRIB_VOL, volume) })?;
+        if let Some(stretched) = &self.stretched {
+            check(unsafe {
+                bass::BASS_ChannelSetAttribute(stretched.handle(), BASS_ATTRIB_VOL, volume)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Sound {
+    fn drop(&mut self) {
+        unsafe { bass::BASS_StreamFree(self.handle) };
+    }
+}
+
+/// A short sound that is loaded into memory all at once, and can be played many times at the same
+/// time. Use this for effects, and [`Sound`] for music.
+pub struct Sample {
+    handle: u32,
+}
+
+impl Sample {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (path_buf, path_flags) = bass_path(path)?;
+        let handle = unsafe {
+            bass::BASS_SampleLoad(
+                0,
+                path_buf.as_ptr() as *const _,
+                0,
+                0,
+                MAX_SAMPLE_PLAYBACKS,
+                BASS_SAMPLE_OVER_POS | path_flags,
+            )
+        };
+        let handle =
+            check_handle(handle).with_context(|| format!("failed to load sample {:?}", path))?;
+
+        Ok(Sample { handle })
+    }
+
+    pub fn handle(&self) -> u32 {
+        self.handle
+    }
+}
+
+impl Drop for Sample {
+    fn drop(&mut self) {
+        unsafe { bass::BASS_SampleFree(self.handle) };
+    }
+}
+
+/// Converts a path into the null-terminated string BASS expects, along with the flags needed to
+/// pass it. On Windows, BASS takes UTF-16 paths so that non-ASCII file names work.
+#[cfg(windows)]
+fn bass_path(path: &Path) -> Result<(Vec<u16>, u32)> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut buf = path.as_os_str().encode_wide().collect::<Vec<_>>();
+    if buf.contains(&0) {
+        bail!("path {:?} contains a null character", path);
+    }
+    buf.push(0);
+    Ok((buf, BASS_UNICODE))
+}
+
+/// Converts a path into the null-terminated string BASS expects, along with the flags needed to
+/// pass it. Everywhere other than Windows, paths are passed as raw bytes.
+#[cfg(not(windows))]
+fn bass_path(path: &Path) -> Result<(Vec<u8>, u32)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buf = path.as_os_str().as_bytes().to_vec();
+    if buf.contains(&0) {
+        bail!("path {:?} contains a null character", path);
+    }
+    buf.push(0);
+    Ok((buf, 0))
+}
+
+fn bytes_to_seconds(handle: u32, bytes: bass::QWORD) -> Result<f64> {
+    let seconds = unsafe { bass::BASS_ChannelBytes2Seconds(handle, bytes) };
+    if seconds < 0.0 {
+        return Err(BassError::last().into());
+    }
+    Ok(seconds)
+}
+
+/// Position of a channel in seconds.
+fn channel_position(handle: u32) -> Result<f64> {
+    let bytes = unsafe { bass::BASS_ChannelGetPosition(handle, BASS_POS_BYTE) };
+    if bytes == !0 {
+        return Err(BassError::last().into());
+    }
+    bytes_to_seconds(handle, bytes)
+}
+
+fn set_channel_position(handle: u32, pos: f64) -> Result<()> {
+    unsafe {
+        let pos_bytes = bass::BASS_ChannelSeconds2Bytes(handle, pos);
+        if pos_bytes == !0 {
+            return Err(BassError::last().into());
+        }
+        check(bass::BASS_ChannelSetPosition(
+            handle,
+            pos_bytes,
+            BASS_POS_BYTE,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Turns the return value of a BASS function that returns a BOOL into a result.
+fn check(result: bass::BOOL) -> Result<(), BassError> {
+    if result != 0 {
+        Ok(())
+    } else {
+        Err(BassError::last())
+    }
+}
+
+/// Turns the return value of a BASS function that returns a handle into a result.
+fn check_handle(handle: u32) -> Result<u32, BassError> {
+    if handle != 0 {
+        Ok(handle)
+    } else {
+        Err(BassError::last())
+    }
+}
+
+macro_rules! bass_errors {
+    ($($variant:ident => $code:ident, $description:expr;)*) => {
+        /// An error reported by BASS through `BASS_ErrorGetCode`.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        pub enum BassError {
+            $($variant,)*
+            /// An error code that isn't in the list above.
+            Other(c_int),
+        }
+
+        impl BassError {
+            pub fn from_code(code: c_int) -> Self {
+                match code {
+                    $($code => BassError::$variant,)*
+                    code => BassError::Other(code),
+                }
+            }
+
+            /// The name of the constant BASS uses for this error.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(BassError::$variant => stringify!($code),)*
+                    BassError::Other(_) => "BASS_ERROR_UNKNOWN",
+                }
+            }
+
+            pub fn description(&self) -> &'static str {
+                match self {
+                    $(BassError::$variant => $description,)*
+                    BassError::Other(_) => "unknown error",
+                }
+            }
+        }
+    };
+}
+
+bass_errors! {
+    Mem => BASS_ERROR_MEM, "memory error";
+    FileOpen => BASS_ERROR_FILEOPEN, "can't open the file";
+    Driver => BASS_ERROR_DRIVER, "can't find a free/valid driver";
+    BufLost => BASS_ERROR_BUFLOST, "the sample buffer was lost";
+    Handle => BASS_ERROR_HANDLE, "invalid handle";
+    Format => BASS_ERROR_FORMAT, "unsupported sample format";
+    Position => BASS_ERROR_POSITION, "invalid position";
+    Init => BASS_ERROR_INIT, "BASS_Init has not been successfully called";
+    Start => BASS_ERROR_START, "BASS_Start has not been successfully called";
+    Ssl => BASS_ERROR_SSL, "SSL/HTTPS support isn't available";
+    Already => BASS_ERROR_ALREADY, "already initialized/paused/whatever";
+    NotAudio => BASS_ERROR_NOTAUDIO, "file does not contain audio";
+    NoChan => BASS_ERROR_NOCHAN, "can't get a free channel";
+    IllType => BASS_ERROR_ILLTYPE, "an illegal type was specified";
+    IllParam => BASS_ERROR_ILLPARAM, "an illegal parameter was specified";
+    No3d => BASS_ERROR_NO3D, "no 3D support";
+    NoEax => BASS_ERROR_NOEAX, "no EAX support";
+    Device => BASS_ERROR_DEVICE, "illegal device number";
+    NoPlay => BASS_ERROR_NOPLAY, "not playing";
+    Freq => BASS_ERROR_FREQ, "illegal sample rate";
+    NotFile => BASS_ERROR_NOTFILE, "the stream is not a file stream";
+    NoHw => BASS_ERROR_NOHW, "no hardware voices available";
+    Empty => BASS_ERROR_EMPTY, "the file has no sample data";
+    NoNet => BASS_ERROR_NONET, "no internet connection could be opened";
+    Create => BASS_ERROR_CREATE, "couldn't create the file";
+    NoFx => BASS_ERROR_NOFX, "effects are not available";
+    NotAvail => BASS_ERROR_NOTAVAIL, "requested data/action is not available";
+    Decode => BASS_ERROR_DECODE, "the channel is/isn't a decoding channel";
+    Dx => BASS_ERROR_DX, "a sufficient DirectX version is not installed";
+    Timeout => BASS_ERROR_TIMEOUT, "connection timed out";
+    FileForm => BASS_ERROR_FILEFORM, "unsupported file format";
+    Speaker => BASS_ERROR_SPEAKER, "unavailable speaker";
+    Version => BASS_ERROR_VERSION, "invalid BASS version";
+    Codec => BASS_ERROR_CODEC, "codec is not available/supported";
+    Ended => BASS_ERROR_ENDED, "the channel/file has ended";
+    Busy => BASS_ERROR_BUSY, "the device is busy";
+    Unstreamable => BASS_ERROR_UNSTREAMABLE, "unstreamable file";
+}
+
+impl BassError {
+    /// The error from the last BASS function that was called on this thread.
+    pub fn last() -> Self {
+        BassError::from_code(unsafe { bass::BASS_ErrorGetCode() })
+    }
+}
+
+impl fmt::Display for BassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BassError::Other(code) => {
+                write!(f, "{} ({}): {}", self.name(), code, self.description())
+            }
+            _ => write!(f, "{}: {}", self.name(), self.description()),
+        }
+    }
+}
+
+impl Error for BassError {}
@@ -0,0 +1,298 @@
+//! The frequencies of a song over time, for drawing its spectrogram.
+//!
+//! Like the waveform, the whole song is analyzed once in the background. An FFT is taken at
+//! regular steps through the song, and its bins are grouped into bands along a frequency scale
+//! so that drawing it only has to look the bands up.
+
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{Context, Result};
+use bass::constants::*;
+
+use super::{bass_path, check, check_handle, set_channel_position, BassError};
+
+/// FFT sizes that BASS can do, along with the flag that asks for each one.
+pub const FFT_SIZES: &[(usize, u32)] = &[
+    (256, BASS_DATA_FFT256),
+    (512, BASS_DATA_FFT512),
+    (1024, BASS_DATA_FFT1024),
+    (2048, BASS_DATA_FFT2048),
+    (4096, BASS_DATA_FFT4096),
+    (8192, BASS_DATA_FFT8192),
+    (16384, BASS_DATA_FFT16384),
+];
+
+/// How many frequency bands each step is split into.
+pub const BANDS: usize = 64;
+
+/// Time between the FFTs, in seconds. The timeline never shows less than about 4ms per column of
+/// the spectrogram, so this is close enough even when it's zoomed all the way in.
+const STEP_DURATION: f64 = 0.01;
+
+/// The lowest frequency shown on the log scale, in Hz.
+const MIN_FREQUENCY: f64 = 20.0;
+
+/// Loudness (in dB) that maps to the bottom and top of the range of a band.
+const DB_RANGE: (f32, f32) = (-90.0, -10.0);
+
+/// How frequencies are laid out from the lowest band to the highest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrequencyScale {
+    Linear,
+    Log,
+    Mel,
+}
+
+impl FrequencyScale {
+    /// The frequency at some fraction of the way up the bands.
+    fn frequency_at(&self, fraction: f64, nyquist: f64) -> f64 {
+        match self {
+            FrequencyScale::Linear => fraction * nyquist,
+            FrequencyScale::Log => MIN_FREQUENCY * (nyquist / MIN_FREQUENCY).powf(fraction),
+            FrequencyScale::Mel => {
+                let max_mel = 2595.0 * (1.0 + nyquist / 700.0).log10();
+                700.0 * (10f64.powf(fraction * max_mel / 2595.0) - 1.0)
+            }
+        }
+    }
+}
+
+/// Which FFT bins go into each band, from the bottom up, as ranges of bin indices. Every band
+/// gets at least one bin, even where the scale spreads the bands thinner than the bins.
+fn band_bins(scale: FrequencyScale, fft_size: usize, sample_rate: u32) -> Vec<(usize, usize)> {
+    let num_bins = fft_size / 2;
+    let nyquist = sample_rate as f64 / 2.0;
+    let bin_width = sample_rate.max(1) as f64 / fft_size as f64;
+    (0..BANDS)
+        .map(|band| {
+            let low = scale.frequency_at(band as f64 / BANDS as f64, nyquist);
+            let high = scale.frequency_at((band + 1) as f64 / BANDS as f64, nyquist);
+            let first = ((low / bin_width).floor() as usize).min(num_bins - 1);
+            let last = ((high / bin_width).ceil() as usize)
+                .max(first + 1)
+                .min(num_bins);
+            (first, last)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Spectrogram {
+    /// How loud each band is at each step, from 0 to 255, lowest band first.
+    steps: Vec<[u8; BANDS]>,
+}
+
+impl Spectrogram {
+    /// Decodes the whole song and takes its FFT every step. This takes a while for long songs,
+    /// so it should be done off of the main thread.
+    pub fn analyze(path: &Path, fft_size: usize, scale: FrequencyScale) -> Result<Self> {
+        let fft_flag = match FFT_SIZES.iter().find(|(size, _)| *size == fft_size) {
+            Some((_, flag)) => *flag,
+            None => bail!("unsupported FFT size {}", fft_size),
+        };
+
+        let (path_buf, path_flags) = bass_path(path)?;
+        let flags = BASS_STREAM_DECODE | BASS_STREAM_PRESCAN | BASS_SAMPLE_FLOAT | path_flags;
+        let decode =
+            unsafe { bass::BASS_StreamCreateFile(0, path_buf.as_ptr() as *mut _, 0, 0, flags) };
+        let decode = check_handle(decode).with_context(|| format!("failed to load {:?}", path))?;
+        let result = Spectrogram::analyze_channel(decode, fft_size, fft_flag, scale);
+        unsafe { bass::BASS_StreamFree(decode) };
+        result
+    }
+
+    fn analyze_channel(
+        decode: u32,
+        fft_size: usize,
+        fft_flag: u32,
+        scale: FrequencyScale,
+    ) -> Result<Self> {
+        let mut info = unsafe { std::mem::zeroed::<bass::BASS_CHANNELINFO>() };
+        check(unsafe { bass::BASS_ChannelGetInfo(decode, &mut info) })?;
+
+        let num_bins = fft_size / 2;
+        let bands = band_bins(scale, fft_size, info.freq);
+
+        let mut steps = Vec::new();
+        let mut bins = vec![0.0f32; num_bins];
+        loop {
+            // the FFT uses up the samples it looks at, so every step seeks back to where it starts
+            let time = steps.len() as f64 * STEP_DURATION;
+            if let Err(err) = set_channel_position(decode, time) {
+                return match err.downcast_ref::<BassError>() {
+                    // past the end of the song
+                    Some(BassError::Position) => Ok(Spectrogram { steps }),
+                    _ => Err(err),
+                };
+            }
+            let result = unsafe {
+                bass::BASS_ChannelGetData(
+                    decode,
+                    bins.as_mut_ptr() as *mut c_void,
+                    fft_flag | BASS_DATA_FFT_REMOVEDC,
+                )
+            };
+            if result == !0 {
+                // right at the end there might not be anything left to decode
+                return Ok(Spectrogram { steps });
+            }
+
+            let mut step = [0u8; BANDS];
+            for (value, (first, last)) in step.iter_mut().zip(bands.iter()) {
+                let magnitude = bins[*first..*last].iter().cloned().fold(0.0, f32::max);
+                let db = 20.0 * magnitude.max(1e-9).log10();
+                let loudness = (db - DB_RANGE.0) / (DB_RANGE.1 - DB_RANGE.0);
+                *value = (loudness.max(0.0).min(1.0) * 255.0) as u8;
+            }
+            steps.push(step);
+        }
+    }
+
+    /// Analyzes the song on another thread. The spectrogram (or why it couldn't be made) shows
+    /// up in the returned slot once it's done.
+    pub fn analyze_in_background(
+        path: PathBuf,
+        fft_size: usize,
+        scale: FrequencyScale,
+    ) -> Arc<Mutex<Option<Result<Arc<Spectrogram>, String>>>> {
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = slot.clone();
+        let spawned = thread::Builder::new()
+            .name("spectrogram".to_owned())
+            .spawn(move || {
+                let result = Spectrogram::analyze(&path, fft_size, scale)
+                    .map(Arc::new)
+                    .map_err(|err| err.to_string());
+                debug!("analyzed the spectrogram of {:?}", path);
+                *thread_slot.lock().unwrap() = Some(result);
+            });
+        if let Err(err) = spawned {
+            *slot.lock().unwrap() = Some(Err(format!("couldn't start analyzing: {}", err)));
+        }
+        slot
+    }
+
+    /// The loudest each band gets between two times (in seconds), from 0 to 1. This is None if
+    /// the span is outside of the song.
+    pub fn bands_between(&self, start: f64, end: f64) -> Option<[f32; BANDS]> {
+        if end < 0.0 {
+            return None;
+        }
+        // a span shorter than a step still shows the step it's in
+        let first = (start.max(0.0) / STEP_DURATION).floor() as usize;
+        let last = ((end / STEP_DURATION).ceil() as usize).max(first + 1);
+        let steps = self.steps.get(first..last.min(self.steps.len()))?;
+        if steps.is_empty() {
+            return None;
+        }
+
+        let mut bands = [0.0f32; BANDS];
+        for step in steps {
+            for (band, value) in bands.iter_mut().zip(step.iter()) {
+                *band = band.max(*value as f32 / 255.0);
+            }
+        }
+        Some(bands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALES: &[FrequencyScale] = &[
+        FrequencyScale::Linear,
+        FrequencyScale::Log,
+        FrequencyScale::Mel,
+    ];
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn scales_span_up_to_nyquist() {
+        for scale in SCALES {
+            assert_close(scale.frequency_at(1.0, 22050.0), 22050.0);
+            let mut last = scale.frequency_at(0.0, 22050.0);
+            for i in 1..=100 {
+                let frequency = scale.frequency_at(i as f64 / 100.0, 22050.0);
+                assert!(frequency > last, "{:?} isn't increasing", scale);
+                last = frequency;
+            }
+        }
+
+        assert_close(FrequencyScale::Linear.frequency_at(0.0, 22050.0), 0.0);
+        assert_close(
+            FrequencyScale::Log.frequency_at(0.0, 22050.0),
+            MIN_FREQUENCY,
+        );
+        assert_close(FrequencyScale::Mel.frequency_at(0.0, 22050.0), 0.0);
+    }
+
+    #[test]
+    fn scales_give_low_frequencies_more_room() {
+        let linear = FrequencyScale::Linear.frequency_at(0.5, 22050.0);
+        let log = FrequencyScale::Log.frequency_at(0.5, 22050.0);
+        let mel = FrequencyScale::Mel.frequency_at(0.5, 22050.0);
+        assert_close(linear, 11025.0);
+        assert_close(log, (MIN_FREQUENCY * 22050.0).sqrt());
+        assert!(log < mel && mel < linear, "{} {} {}", log, mel, linear);
+    }
+
+    #[test]
+    fn linear_bands_split_bins_evenly() {
+        // 128 bins over 64 bands
+        let bins = band_bins(FrequencyScale::Linear, 256, 44100);
+        let expected = (0..BANDS).map(|i| (2 * i, 2 * i + 2)).collect::<Vec<_>>();
+        assert_eq!(bins, expected);
+    }
+
+    #[test]
+    fn every_band_has_bins() {
+        for scale in SCALES {
+            for (fft_size, _) in FFT_SIZES {
+                let bins = band_bins(*scale, *fft_size, 44100);
+                assert_eq!(bins.len(), BANDS);
+                for (first, last) in bins.iter() {
+                    assert!(first < last && *last <= fft_size / 2, "{:?}", bins);
+                }
+                // no band starts below the one under it
+                assert!(bins.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+                assert_eq!(bins.last().unwrap().1, fft_size / 2);
+            }
+        }
+    }
+
+    /// A spectrogram where every band of step `i` is `i * 10` loud.
+    fn ramp(steps: usize) -> Spectrogram {
+        Spectrogram {
+            steps: (0..steps).map(|i| [(i * 10) as u8; BANDS]).collect(),
+        }
+    }
+
+    #[test]
+    fn bands_between_takes_the_loudest_step() {
+        let spectrogram = ramp(10);
+        let bands = spectrogram.bands_between(0.0, 0.005).unwrap();
+        assert!(bands.iter().all(|band| *band == 0.0));
+
+        let bands = spectrogram.bands_between(0.01, 0.03).unwrap();
+        assert!(bands.iter().all(|band| *band == 20.0 / 255.0));
+
+        // a span shorter than a step shows the step it's in
+        let bands = spectrogram.bands_between(0.052, 0.054).unwrap();
+        assert!(bands.iter().all(|band| *band == 50.0 / 255.0));
+    }
+
+    #[test]
+    fn bands_between_outside_the_song() {
+        let spectrogram = ramp(10);
+        assert!(spectrogram.bands_between(-0.02, -0.01).is_none());
+        assert!(spectrogram.bands_between(0.2, 0.3).is_none());
+        assert!(spectrogram.bands_between(-0.01, 0.005).is_some());
+    }
+}
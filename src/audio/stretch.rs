@@ -0,0 +1,390 @@
+//! Pitch-preserving time stretching, for playing songs back slower than normal.
+//!
+//! This uses WSOLA (waveform similarity overlap-add): the song is cut into overlapping, windowed
+//! frames which are laid down at a fixed spacing in the output, but picked from the input at a
+//! spacing that's scaled by the playback rate. Each frame is nudged by a few milliseconds so that
+//! it lines up with the end of the previous one, which avoids the phasing you'd get from a plain
+//! overlap-add.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{Context, Result};
+use bass::constants::*;
+
+use super::{bass_path, channel_position, check, check_handle, set_channel_position};
+
+/// Length of each frame, in seconds.
+const FRAME_LENGTH: f64 = 0.05;
+
+/// How far (in seconds) a frame is allowed to be moved from where it would normally be taken from
+/// to line it up with the previous frame.
+const TOLERANCE: f64 = 0.01;
+
+/// How much is decoded from the song at a time, in sample frames.
+const DECODE_CHUNK: usize = 4096;
+
+/// Only every this many samples are compared when lining up frames, which is plenty for finding
+/// the best offset and a lot faster.
+const CORRELATION_STRIDE: usize = 4;
+
+/// A song that's played back at a different speed, without changing its pitch.
+///
+/// The song is decoded on a separate decoding stream, and stretched on the fly by a user stream
+/// that BASS pulls data from as it plays.
+pub struct StretchedStream {
+    decode: u32,
+    /// The stream that's actually played. This is replaced whenever the position or rate
+    /// changes, to get rid of anything that was buffered from before.
+    output: Cell<u32>,
+    freq: u32,
+    chans: u32,
+    /// Owned by this struct. BASS gets a pointer to it to pass back to the stream callback.
+    stretcher: *mut Mutex<Stretcher>,
+}
+
+impl StretchedStream {
+    pub fn create(path: &Path, rate: f64) -> Result<Self> {
+        let (path_buf, path_flags) = bass_path(path)?;
+        let flags = BASS_STREAM_DECODE | BASS_STREAM_PRESCAN | BASS_SAMPLE_FLOAT | path_flags;
+        let decode =
+            unsafe { bass::BASS_StreamCreateFile(0, path_buf.as_ptr() as *mut _, 0, 0, flags) };
+        let decode = check_handle(decode).with_context(|| format!("failed to load {:?}", path))?;
+
+        let mut info = unsafe { std::mem::zeroed::<bass::BASS_CHANNELINFO>() };
+        if let Err(err) = check(unsafe { bass::BASS_ChannelGetInfo(decode, &mut info) }) {
+            unsafe { bass::BASS_StreamFree(decode) };
+            return Err(err.into());
+        }
+
+        let stretcher = Stretcher::new(decode, info.freq, info.chans as usize, rate);
+        let stream = StretchedStream {
+            decode,
+            output: Cell::new(0),
+            freq: info.freq,
+            chans: info.chans,
+            stretcher: Box::into_raw(Box::new(Mutex::new(stretcher))),
+        };
+        stream.output.set(stream.create_output()?);
+        Ok(stream)
+    }
+
+    /// The handle of the stream that should be played.
+    pub fn handle(&self) -> u32 {
+        self.output.get()
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.lock().rate
+    }
+
+    /// Position in the song in seconds, taking into account how much of the stretched audio has
+    /// actually been heard so far.
+    pub fn position(&self) -> Result<f64> {
+        let played = channel_position(self.output.get())?;
+        let stretcher = self.lock();
+        Ok(stretcher.base_time + played * stretcher.rate)
+    }
+
+    pub fn set_position(&self, pos: f64) -> Result<()> {
+        let rate = self.rate();
+        self.restart(pos, rate)
+    }
+
+    pub fn set_rate(&self, rate: f64) -> Result<()> {
+        let pos = self.position()?;
+        self.restart(pos, rate)
+    }
+
+    /// Starts stretching from the given position with the given rate, keeping the stream playing
+    /// (or paused) and at the same volume as before.
+    fn restart(&self, pos: f64, rate: f64) -> Result<()> {
+        let old_output = self.output.get();
+        let was_playing = unsafe { bass::BASS_ChannelIsActive(old_output) } == BASS_ACTIVE_PLAYING;
+        let mut volume = 1.0;
+        check(unsafe { bass::BASS_ChannelGetAttribute(old_output, BASS_ATTRIB_VOL, &mut volume) })?;
+
+        // once this returns, the callback won't be called for the old stream anymore, so it's
+        // safe to mess with the stretcher
+        unsafe { bass::BASS_StreamFree(old_output) };
+
+        set_channel_position(self.decode, pos)?;
+        self.lock().reset(pos, rate);
+
+        let output = self.create_output()?;
+        self.output.set(output);
+        check(unsafe { bass::BASS_ChannelSetAttribute(output, BASS_ATTRIB_VOL, volume) })?;
+        if was_playing {
+            check(unsafe { bass::BASS_ChannelPlay(output, 0) })?;
+        }
+        Ok(())
+    }
+
+    fn create_output(&self) -> Result<u32> {
+        let output = unsafe {
+            bass::BASS_StreamCreate(
+                self.freq,
+                self.chans,
+                BASS_SAMPLE_FLOAT,
+                stream_proc,
+                self.stretcher as *mut c_void,
+            )
+        };
+        Ok(check_handle(output)?)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Stretcher> {
+        let stretcher = unsafe { &*self.stretcher };
+        // the stretcher doesn't panic while holding the lock, but if it did, its state would
+        // still be usable
+        stretcher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for StretchedStream {
+    fn drop(&mut self) {
+        unsafe {
+            bass::BASS_StreamFree(self.output.get());
+            drop(Box::from_raw(self.stretcher));
+            bass::BASS_StreamFree(self.decode);
+        }
+    }
+}
+
+/// Called by BASS whenever the output stream needs more data.
+extern "C" fn stream_proc(_: u32, buffer: *mut c_void, length: u32, user: *mut c_void) -> u32 {
+    let stretcher = unsafe { &*(user as *const Mutex<Stretcher>) };
+    let mut stretcher = stretcher
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let out = unsafe { slice::from_raw_parts_mut(buffer as *mut f32, length as usize / 4) };
+    let (written, ended) = stretcher.read(out);
+
+    let mut result = (written * 4) as u32;
+    if ended {
+        result |= BASS_STREAMPROC_END;
+    }
+    result
+}
+
+struct Stretcher {
+    decode: u32,
+    chans: usize,
+    rate: f64,
+    /// Position in the song (in seconds) where stretching started from.
+    base_time: f64,
+
+    /// Length of a frame, in sample frames.
+    frame_len: usize,
+    /// Distance between frames in the output, in sample frames.
+    hop: usize,
+    /// How far frames can be moved to line them up, in sample frames.
+    tolerance: usize,
+    window: Vec<f32>,
+
+    /// Decoded samples that haven't been used yet, interleaved.
+    input: Vec<f32>,
+    /// Which sample frame (counting from the base time) the start of the input is.
+    input_start: usize,
+    source_ended: bool,
+
+    /// Where the next frame would be taken from if it didn't need lining up.
+    nominal_pos: f64,
+    /// Where the last frame was actually taken from.
+    prev_pos: Option<usize>,
+
+    /// Frames are added together in here, and taken out once nothing else overlaps them.
+    accumulator: Vec<f32>,
+    /// Stretched samples that are ready to be played, interleaved.
+    ready: VecDeque<f32>,
+    flushed: bool,
+}
+
+impl Stretcher {
+    fn new(decode: u32, freq: u32, chans: usize, rate: f64) -> Self {
+        let frame_len = (freq as f64 * FRAME_LENGTH) as usize & !1;
+        let hop = frame_len / 2;
+
+        // a periodic hann window adds up to exactly 1 when the frames overlap by half
+        let window = (0..frame_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+            .collect();
+
+        Stretcher {
+            decode,
+            chans: chans.max(1),
+            rate,
+            base_time: 0.0,
+            frame_len,
+            hop,
+            tolerance: (freq as f64 * TOLERANCE) as usize,
+            window,
+            input: Vec::new(),
+            input_start: 0,
+            source_ended: false,
+            nominal_pos: 0.0,
+            prev_pos: None,
+            accumulator: vec![0.0; frame_len * chans.max(1)],
+            ready: VecDeque::new(),
+            flushed: false,
+        }
+    }
+
+    /// Forgets everything, for when the song was seeked. The decoding stream should already be at
+    /// the new position.
+    fn reset(&mut self, base_time: f64, rate: f64) {
+        self.base_time = base_time;
+        self.rate = rate;
+        self.input.clear();
+        self.input_start = 0;
+        self.source_ended = false;
+        self.nominal_pos = 0.0;
+        self.prev_pos = None;
+        self.accumulator.clear();
+        self.accumulator.resize(self.frame_len * self.chans, 0.0);
+        self.ready.clear();
+        self.flushed = false;
+    }
+
+    /// Fills the buffer with as many stretched samples as are available. Returns how many samples
+    /// were written, and whether the song is over.
+    fn read(&mut self, out: &mut [f32]) -> (usize, bool) {
+        while self.ready.len() < out.len() && self.process_frame() {}
+
+        if self.ready.len() < out.len() && !self.flushed {
+            // nothing left to overlap with, so whatever's in the accumulator is done
+            self.ready.extend(self.accumulator.drain(..));
+            self.flushed = true;
+        }
+
+        let written = self.ready.len().min(out.len());
+        for (out, sample) in out.iter_mut().zip(self.ready.drain(..written)) {
+            *out = sample;
+        }
+
+        let ended = self.flushed && self.ready.is_empty();
+        (written, ended)
+    }
+
+    fn input_end(&self) -> usize {
+        self.input_start + self.input.len() / self.chans
+    }
+
+    /// Decodes more of the song until the input reaches the given sample frame, or the song ends.
+    fn fill_input(&mut self, until: usize) {
+        let mut chunk = vec![0.0f32; DECODE_CHUNK * self.chans];
+        while !self.source_ended && self.input_end() < until {
+            let bytes = (chunk.len() * 4) as u32;
+            let result = unsafe {
+                bass::BASS_ChannelGetData(
+                    self.decode,
+                    chunk.as_mut_ptr() as *mut c_void,
+                    bytes | BASS_DATA_FLOAT,
+                )
+            };
+
+            if result == !0 || result == 0 {
+                self.source_ended = true;
+            } else {
+                let samples = result as usize / 4;
+                self.input.extend_from_slice(&chunk[..samples]);
+            }
+        }
+    }
+
+    /// The given sample frame mixed down to mono, or silence if it's not in the input.
+    fn mono(&self, frame: usize) -> f32 {
+        if frame < self.input_start || frame >= self.input_end() {
+            return 0.0;
+        }
+
+        let start = (frame - self.input_start) * self.chans;
+        self.input[start..start + self.chans].iter().sum::<f32>() / self.chans as f32
+    }
+
+    fn sample(&self, frame: usize, chan: usize) -> f32 {
+        if frame < self.input_start || frame >= self.input_end() {
+            return 0.0;
+        }
+
+        self.input[(frame - self.input_start) * self.chans + chan]
+    }
+
+    /// Finds where near the nominal position the next frame lines up best with the previous one.
+    fn best_position(&self, nominal: usize) -> usize {
+        let prev_pos = match self.prev_pos {
+            Some(pos) => pos,
+            None => return nominal,
+        };
+
+        // this is where the previous frame would have naturally continued
+        let target = prev_pos + self.hop;
+        let overlap = self.frame_len - self.hop;
+
+        let mut best = nominal;
+        let mut best_score = f32::MIN;
+        let start = nominal.saturating_sub(self.tolerance).max(self.input_start);
+        for candidate in start..=nominal + self.tolerance {
+            let mut dot = 0.0;
+            let mut energy = 0.0;
+            for i in (0..overlap).step_by(CORRELATION_STRIDE) {
+                let sample = self.mono(candidate + i);
+                dot += sample * self.mono(target + i);
+                energy += sample * sample;
+            }
+
+            let score = dot / (energy + 1e-6).sqrt();
+            if score > best_score {
+                best = candidate;
+                best_score = score;
+            }
+        }
+
+        best
+    }
+
+    /// Adds another frame to the output. Returns false if the song is over.
+    fn process_frame(&mut self) -> bool {
+        let nominal = self.nominal_pos.round() as usize;
+        self.fill_input(nominal + self.tolerance + self.frame_len);
+        if self.source_ended && nominal >= self.input_end() {
+            return false;
+        }
+
+        let pos = self.best_position(nominal);
+        for i in 0..self.frame_len {
+            let weight = self.window[i];
+            for chan in 0..self.chans {
+                self.accumulator[i * self.chans + chan] += weight * self.sample(pos + i, chan);
+            }
+        }
+
+        // the first hop of the accumulator won't be overlapped by any more frames
+        let done = self.hop * self.chans;
+        self.ready.extend(self.accumulator.drain(..done));
+        self.accumulator.resize(self.frame_len * self.chans, 0.0);
+
+        self.prev_pos = Some(pos);
+        self.nominal_pos += self.hop as f64 * self.rate;
+
+        // throw away input that won't be looked at again
+        let keep_from = (self.nominal_pos as usize)
+            .saturating_sub(self.tolerance)
+            .min(pos + self.hop);
+        if keep_from > self.input_start + DECODE_CHUNK {
+            let drop = (keep_from - self.input_start).min(self.input.len() / self.chans);
+            self.input.drain(..drop * self.chans);
+            self.input_start += drop;
+        }
+
+        true
+    }
+}
@@ -0,0 +1,413 @@
+//! Guessing the tempo of a song from its audio.
+//!
+//! The song is turned into an onset envelope, which spikes wherever something new starts
+//! playing. The autocorrelation of the envelope gives a rough beat length, which is then refined
+//! by trying nearby tempos and phases and seeing which one lines up with the most onsets. Last,
+//! the beats are grouped into measures of 3 or 4 by looking for the strongest accents.
+
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{Context, Result};
+use bass::constants::*;
+
+use super::{bass_path, check, check_handle, set_channel_position};
+
+/// How many sample frames go into each point of the onset envelope. At 44.1kHz this is about
+/// 12ms.
+const HOP: usize = 512;
+
+/// Tempos that are considered, in BPM.
+const BPM_RANGE: (f64, f64) = (60.0, 240.0);
+
+/// Tempos near this are preferred when the autocorrelation can't decide between a tempo and
+/// double or half of it.
+const PREFERRED_BPM: f64 = 120.0;
+
+/// How far (as a fraction) around the rough tempo the fine search goes.
+const FINE_RANGE: f64 = 0.02;
+
+/// Step of the fine search, in BPM.
+const FINE_STEP: f64 = 0.01;
+
+/// An uninherited timing point that fits a stretch of the song.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoEstimate {
+    pub bpm: f64,
+    /// Time of the first downbeat, in seconds.
+    pub offset: f64,
+    pub meter: u32,
+    /// From 0 to 1, how much more the best guess lines up with the onsets than others do.
+    pub confidence: f64,
+}
+
+/// The onset envelope of a stretch of the song.
+struct Envelope {
+    values: Vec<f32>,
+    /// Length of each point in the envelope, in seconds.
+    hop_duration: f64,
+}
+
+impl Envelope {
+    fn decode(path: &Path, start: f64, end: f64) -> Result<Self> {
+        let (path_buf, path_flags) = bass_path(path)?;
+        let flags = BASS_STREAM_DECODE | BASS_STREAM_PRESCAN | BASS_SAMPLE_FLOAT | path_flags;
+        let decode =
+            unsafe { bass::BASS_StreamCreateFile(0, path_buf.as_ptr() as *mut _, 0, 0, flags) };
+        let decode = check_handle(decode).with_context(|| format!("failed to load {:?}", path))?;
+        let result = Envelope::decode_channel(decode, start, end);
+        unsafe { bass::BASS_StreamFree(decode) };
+        result
+    }
+
+    fn decode_channel(decode: u32, start: f64, end: f64) -> Result<Self> {
+        let mut info = unsafe { std::mem::zeroed::<bass::BASS_CHANNELINFO>() };
+        check(unsafe { bass::BASS_ChannelGetInfo(decode, &mut info) })?;
+        let chans = (info.chans as usize).max(1);
+        let hop_duration = HOP as f64 / info.freq.max(1) as f64;
+        set_channel_position(decode, start.max(0.0))?;
+
+        // high frequency energy of each hop, which mostly picks up on drums and other attacks
+        let max_hops = ((end - start) / hop_duration).max(0.0) as usize;
+        let mut energies = Vec::new();
+        let mut chunk = vec![0.0f32; HOP * chans];
+        let mut previous = 0.0;
+        while energies.len() < max_hops {
+            let bytes = (chunk.len() * 4) as u32;
+            let result = unsafe {
+                bass::BASS_ChannelGetData(
+                    decode,
+                    chunk.as_mut_ptr() as *mut c_void,
+                    bytes | BASS_DATA_FLOAT,
+                )
+            };
+            if result == !0 || result == 0 {
+                break;
+            }
+
+            let samples = result as usize / 4;
+            let mut energy = 0.0;
+            for frame in chunk[..samples].chunks_exact(chans) {
+                let sample = frame.iter().sum::<f32>() / chans as f32;
+                energy += (sample - previous) * (sample - previous);
+                previous = sample;
+            }
+            energies.push((1.0 + 1000.0 * energy / HOP as f32).ln());
+        }
+
+        // onsets are where the energy goes up, compared to what's around them
+        let flux = std::iter::once(0.0)
+            .chain(energies.windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)))
+            .collect::<Vec<_>>();
+        let radius = 8;
+        let values = (0..flux.len())
+            .map(|i| {
+                let window = &flux[i.saturating_sub(radius)..(i + radius + 1).min(flux.len())];
+                let mean = window.iter().sum::<f32>() / window.len() as f32;
+                (flux[i] - mean).max(0.0)
+            })
+            .collect();
+
+        Ok(Envelope {
+            values,
+            hop_duration,
+        })
+    }
+
+    fn duration(&self) -> f64 {
+        self.values.len() as f64 * self.hop_duration
+    }
+
+    /// The envelope at a time (in seconds from the start of the stretch), interpolated between
+    /// points.
+    fn at(&self, time: f64) -> f32 {
+        let pos = time / self.hop_duration;
+        let idx = pos.floor();
+        if idx < 0.0 || idx as usize + 1 >= self.values.len() {
+            return 0.0;
+        }
+        let t = (pos - idx) as f32;
+        let idx = idx as usize;
+        self.values[idx] * (1.0 - t) + self.values[idx + 1] * t
+    }
+
+    /// The strongest point of the envelope within a hop of the given time.
+    fn peak_near(&self, time: f64) -> f32 {
+        (-1..=1)
+            .map(|i| self.at(time + i as f64 * self.hop_duration))
+            .fold(0.0, f32::max)
+    }
+
+    /// A rough tempo from the autocorrelation of the envelope, in BPM.
+    fn rough_bpm(&self) -> Option<f64> {
+        let lag_of = |bpm: f64| 60.0 / (bpm * self.hop_duration);
+        let min_lag = lag_of(BPM_RANGE.1).floor().max(1.0) as usize;
+        let max_lag = lag_of(BPM_RANGE.0).ceil() as usize;
+        if self.values.len() < max_lag * 4 {
+            return None;
+        }
+
+        let autocorrelation = |lag: usize| {
+            let n = self.values.len() - lag;
+            let sum = (0..n)
+                .map(|i| self.values[i] * self.values[i + lag])
+                .sum::<f32>();
+            sum as f64 / n as f64
+        };
+        // a beat rarely lasts a whole number of hops, so its peak can be split between two lags.
+        // each lag also counts the ones next to it so that doesn't lose out to a multiple of the
+        // beat that happens to land on a single lag.
+        let raw_scores = (min_lag - 1..=max_lag + 2)
+            .map(autocorrelation)
+            .collect::<Vec<_>>();
+        let scores = raw_scores
+            .windows(3)
+            .map(|window| window.iter().sum::<f64>())
+            .collect::<Vec<_>>();
+
+        let weighted = |i: usize| {
+            let bpm = 60.0 / ((min_lag + i) as f64 * self.hop_duration);
+            let octaves = (bpm / PREFERRED_BPM).log2();
+            scores[i] * (-0.5 * octaves * octaves).exp()
+        };
+        let best = (1..scores.len() - 1).max_by(|a, b| {
+            weighted(*a)
+                .partial_cmp(&weighted(*b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+
+        // the peak is usually between two lags
+        let (left, mid, right) = (scores[best - 1], scores[best], scores[best + 1]);
+        let denominator = left - 2.0 * mid + right;
+        let shift = if denominator.abs() > f64::EPSILON {
+            (0.5 * (left - right) / denominator).max(-0.5).min(0.5)
+        } else {
+            0.0
+        };
+        let lag = (min_lag + best) as f64 + shift;
+        Some(60.0 / (lag * self.hop_duration))
+    }
+
+    /// How well beats of the given length starting at the given phase line up with onsets.
+    fn comb_score(&self, beat: f64, phase: f64) -> f64 {
+        let beats = ((self.duration() - phase) / beat).floor().max(0.0) as usize;
+        if beats == 0 {
+            return 0.0;
+        }
+        let sum = (0..beats)
+            .map(|k| self.at(phase + k as f64 * beat) as f64)
+            .sum::<f64>();
+        sum / beats as f64
+    }
+}
+
+impl TempoEstimate {
+    /// Guesses the timing of the part of the song between two times (in seconds).
+    pub fn detect(path: &Path, start: f64, end: f64) -> Result<Self> {
+        let envelope = Envelope::decode(path, start, end)?;
+        TempoEstimate::from_envelope(&envelope, start)
+    }
+
+    /// The last downbeat at or before some time (in seconds), counting whole measures back (or
+    /// ahead) from the detected one.
+    pub fn downbeat_at_or_before(&self, time: f64) -> f64 {
+        let measure = 60.0 / self.bpm * self.meter.max(1) as f64;
+        // a time right on a downbeat shouldn't round down to the one before it
+        let measures = ((time - self.offset) / measure + 1e-9).floor();
+        self.offset + measures * measure
+    }
+
+    /// Guesses the timing from the onset envelope of a stretch of the song that starts at the
+    /// given time (in seconds).
+    fn from_envelope(envelope: &Envelope, start: f64) -> Result<Self> {
+        let rough_bpm = envelope
+            .rough_bpm()
+            .ok_or_else(|| anyhow!("this part of the song is too short to find its tempo"))?;
+
+        // try tempos around the rough one, at every phase
+        let phase_step = envelope.hop_duration / 2.0;
+        let steps = (rough_bpm * FINE_RANGE / FINE_STEP).ceil() as i64;
+        let mut best = (rough_bpm, 0.0, 0.0, 0.0);
+        for step in -steps..=steps {
+            let bpm = rough_bpm + step as f64 * FINE_STEP;
+            let beat = 60.0 / bpm;
+            let phases = (beat / phase_step).ceil() as usize;
+            let scores = (0..phases)
+                .map(|i| envelope.comb_score(beat, i as f64 * phase_step))
+                .collect::<Vec<_>>();
+            let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+            for (i, score) in scores.iter().enumerate() {
+                if *score > best.2 {
+                    best = (bpm, i as f64 * phase_step, *score, mean);
+                }
+            }
+        }
+        let (bpm, phase, score, mean) = best;
+        if score <= 0.0 {
+            bail!("couldn't find any beats in this part of the song");
+        }
+        let beat = 60.0 / bpm;
+
+        // the first beat of each measure is usually the loudest
+        let accents = |meter: usize| {
+            let mut sums = vec![0.0; meter];
+            let mut counts = vec![0; meter];
+            let mut k = 0;
+            while phase + k as f64 * beat < envelope.duration() {
+                sums[k % meter] += envelope.peak_near(phase + k as f64 * beat) as f64;
+                counts[k % meter] += 1;
+                k += 1;
+            }
+            let averages = sums
+                .iter()
+                .zip(counts.iter())
+                .map(|(sum, count)| sum / (*count as f64).max(1.0))
+                .collect::<Vec<_>>();
+            let mean = averages.iter().sum::<f64>() / meter as f64;
+            let (downbeat, strongest) =
+                averages
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .fold(
+                        (0, 0.0),
+                        |best, (i, a)| if a > best.1 { (i, a) } else { best },
+                    );
+            let contrast = if mean > 0.0 { strongest / mean } else { 1.0 };
+            (downbeat, contrast)
+        };
+        let (downbeat_4, contrast_4) = accents(4);
+        let (downbeat_3, contrast_3) = accents(3);
+        let (meter, downbeat) = if contrast_3 > contrast_4 * 1.1 {
+            (3, downbeat_3)
+        } else {
+            (4, downbeat_4)
+        };
+
+        Ok(TempoEstimate {
+            bpm: (bpm * 100.0).round() / 100.0,
+            offset: start.max(0.0) + phase + downbeat as f64 * beat,
+            meter,
+            confidence: ((score - mean) / score).max(0.0).min(1.0),
+        })
+    }
+
+    /// Guesses the timing on another thread. The result (or why it couldn't be found) shows up
+    /// in the returned slot once it's done.
+    pub fn detect_in_background(
+        path: PathBuf,
+        start: f64,
+        end: f64,
+    ) -> Arc<Mutex<Option<Result<TempoEstimate, String>>>> {
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = slot.clone();
+        let spawned = thread::Builder::new()
+            .name("tempo detection".to_owned())
+            .spawn(move || {
+                let result =
+                    TempoEstimate::detect(&path, start, end).map_err(|err| err.to_string());
+                debug!("detected tempo of {:?}: {:?}", path, result);
+                *thread_slot.lock().unwrap() = Some(result);
+            });
+        if let Err(err) = spawned {
+            *slot.lock().unwrap() = Some(Err(format!("couldn't start detecting: {}", err)));
+        }
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An envelope with a click on every beat, where the first beat of each measure is louder.
+    fn click_track(bpm: f64, offset: f64, meter: usize, duration: f64) -> Envelope {
+        let hop_duration = HOP as f64 / 44100.0;
+        let mut values = vec![0.0; (duration / hop_duration) as usize];
+        let beat = 60.0 / bpm;
+        let mut k = 0;
+        while offset + k as f64 * beat < duration {
+            let idx = ((offset + k as f64 * beat) / hop_duration).round() as usize;
+            if let Some(value) = values.get_mut(idx) {
+                *value = if k % meter == 0 { 2.0 } else { 1.0 };
+            }
+            k += 1;
+        }
+        Envelope {
+            values,
+            hop_duration,
+        }
+    }
+
+    fn assert_estimate(envelope: &Envelope, start: f64, bpm: f64, offset: f64, meter: u32) {
+        let estimate = TempoEstimate::from_envelope(envelope, start).unwrap();
+        assert!((estimate.bpm - bpm).abs() < 0.1, "{:?}", estimate);
+        assert!(
+            (estimate.offset - offset).abs() <= envelope.hop_duration,
+            "{:?}",
+            estimate
+        );
+        assert_eq!(estimate.meter, meter, "{:?}", estimate);
+    }
+
+    #[test]
+    fn rough_bpm_of_click_track() {
+        let envelope = click_track(150.0, 0.3, 4, 30.0);
+        let bpm = envelope.rough_bpm().unwrap();
+        assert!((bpm - 150.0).abs() < 3.0, "{}", bpm);
+    }
+
+    #[test]
+    fn comb_score_prefers_the_beat() {
+        let envelope = click_track(150.0, 0.3, 4, 30.0);
+        let on_beat = envelope.comb_score(0.4, 0.3);
+        let off_beat = envelope.comb_score(0.4, 0.5);
+        assert!(on_beat > off_beat * 2.0, "{} {}", on_beat, off_beat);
+    }
+
+    #[test]
+    fn detects_four_four() {
+        let envelope = click_track(150.0, 0.3, 4, 30.0);
+        assert_estimate(&envelope, 0.0, 150.0, 0.3, 4);
+        // the offset is relative to where the stretch starts in the song
+        assert_estimate(&envelope, 10.0, 150.0, 10.3, 4);
+    }
+
+    #[test]
+    fn detects_three_four() {
+        let envelope = click_track(100.0, 0.25, 3, 30.0);
+        assert_estimate(&envelope, 0.0, 100.0, 0.25, 3);
+    }
+
+    #[test]
+    fn downbeat_at_or_before() {
+        let estimate = TempoEstimate {
+            bpm: 120.0,
+            offset: 10.3,
+            meter: 4,
+            confidence: 1.0,
+        };
+        // measures are 2 seconds long
+        assert!((estimate.downbeat_at_or_before(10.0) - 8.3).abs() < 1e-9);
+        assert!((estimate.downbeat_at_or_before(10.3) - 10.3).abs() < 1e-9);
+        assert!((estimate.downbeat_at_or_before(6.3) - 6.3).abs() < 1e-9);
+        assert!((estimate.downbeat_at_or_before(13.0) - 12.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fails_without_onsets() {
+        let envelope = Envelope {
+            values: vec![0.0; 4000],
+            hop_duration: HOP as f64 / 44100.0,
+        };
+        assert!(TempoEstimate::from_envelope(&envelope, 0.0).is_err());
+    }
+
+    #[test]
+    fn fails_on_short_stretches() {
+        let envelope = click_track(150.0, 0.0, 4, 1.0);
+        assert!(TempoEstimate::from_envelope(&envelope, 0.0).is_err());
+    }
+}
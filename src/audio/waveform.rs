@@ -0,0 +1,259 @@
+//! A summary of how loud a song is over time, for drawing its waveform.
+//!
+//! The song is split into small buckets of samples, and each bucket keeps the lowest and highest
+//! sample in it along with their RMS. Each level after the first merges pairs of buckets from the
+//! level before it, so drawing a zoomed out waveform doesn't have to go through every bucket.
+
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{Context, Result};
+use bass::constants::*;
+
+use super::{bass_path, check, check_handle};
+
+/// How many sample frames go into each bucket of the most detailed level. At 44.1kHz this is
+/// about 1.5ms, which is finer than the timeline ever gets zoomed in.
+const BUCKET_FRAMES: usize = 64;
+
+/// How much is decoded from the song at a time, in sample frames.
+const DECODE_CHUNK: usize = 16384;
+
+/// The loudness of one stretch of the song, with the channels mixed down to mono.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+impl Peak {
+    fn merge(&self, other: &Peak) -> Peak {
+        Peak {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            rms: ((self.rms * self.rms + other.rms * other.rms) / 2.0).sqrt(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Waveform {
+    /// Length of a bucket in the most detailed level, in seconds.
+    bucket_duration: f64,
+    /// Every level has half as many buckets as the one before it.
+    levels: Vec<Vec<Peak>>,
+}
+
+impl Waveform {
+    /// Decodes the whole song and summarizes it. This takes a while for long songs, so it should
+    /// be done off of the main thread.
+    pub fn decode(path: &Path) -> Result<Self> {
+        let (path_buf, path_flags) = bass_path(path)?;
+        let flags = BASS_STREAM_DECODE | BASS_SAMPLE_FLOAT | path_flags;
+        let decode =
+            unsafe { bass::BASS_StreamCreateFile(0, path_buf.as_ptr() as *mut _, 0, 0, flags) };
+        let decode = check_handle(decode).with_context(|| format!("failed to load {:?}", path))?;
+
+        let mut info = unsafe { std::mem::zeroed::<bass::BASS_CHANNELINFO>() };
+        if let Err(err) = check(unsafe { bass::BASS_ChannelGetInfo(decode, &mut info) }) {
+            unsafe { bass::BASS_StreamFree(decode) };
+            return Err(err.into());
+        }
+        let chans = (info.chans as usize).max(1);
+
+        let mut buckets = Vec::new();
+        let mut current = Peak::default();
+        let mut sum_squares = 0.0;
+        let mut frames = 0;
+        let mut chunk = vec![0.0f32; DECODE_CHUNK * chans];
+        loop {
+            let bytes = (chunk.len() * 4) as u32;
+            let result = unsafe {
+                bass::BASS_ChannelGetData(
+                    decode,
+                    chunk.as_mut_ptr() as *mut c_void,
+                    bytes | BASS_DATA_FLOAT,
+                )
+            };
+            if result == !0 || result == 0 {
+                break;
+            }
+
+            let samples = result as usize / 4;
+            for frame in chunk[..samples].chunks_exact(chans) {
+                let sample = frame.iter().sum::<f32>() / chans as f32;
+                current.min = current.min.min(sample);
+                current.max = current.max.max(sample);
+                sum_squares += sample * sample;
+                frames += 1;
+
+                if frames == BUCKET_FRAMES {
+                    current.rms = (sum_squares / frames as f32).sqrt();
+                    buckets.push(current);
+                    current = Peak::default();
+                    sum_squares = 0.0;
+                    frames = 0;
+                }
+            }
+        }
+        if frames > 0 {
+            current.rms = (sum_squares / frames as f32).sqrt();
+            buckets.push(current);
+        }
+        unsafe { bass::BASS_StreamFree(decode) };
+
+        let bucket_duration = BUCKET_FRAMES as f64 / info.freq.max(1) as f64;
+        Ok(Waveform::from_buckets(buckets, bucket_duration))
+    }
+
+    /// Builds the coarser levels on top of the most detailed one.
+    fn from_buckets(buckets: Vec<Peak>, bucket_duration: f64) -> Self {
+        let mut levels = vec![buckets];
+        while levels.last().map_or(false, |level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => a.merge(b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Waveform {
+            bucket_duration,
+            levels,
+        }
+    }
+
+    /// Decodes the song on another thread. The waveform shows up in the returned slot once it's
+    /// done.
+    pub fn decode_in_background(path: PathBuf) -> Arc<Mutex<Option<Arc<Waveform>>>> {
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = slot.clone();
+        let spawned = thread::Builder::new()
+            .name("waveform".to_owned())
+            .spawn(move || match Waveform::decode(&path) {
+                Ok(waveform) => {
+                    debug!("decoded waveform of {:?}", path);
+                    *thread_slot.lock().unwrap() = Some(Arc::new(waveform));
+                }
+                Err(err) => warn!("couldn't decode the waveform of {:?}: {}", path, err),
+            });
+        if let Err(err) = spawned {
+            warn!("couldn't start decoding the waveform: {}", err);
+        }
+        slot
+    }
+
+    /// The loudness between two times (in seconds), taken from the coarsest level that still has
+    /// at least one bucket in that span.
+    pub fn peak_between(&self, start: f64, end: f64) -> Option<Peak> {
+        if end <= start || end < 0.0 {
+            return None;
+        }
+
+        let buckets = ((end - start) / self.bucket_duration).max(1.0);
+        let level = (buckets.log2().floor() as usize).min(self.levels.len() - 1);
+        let bucket_duration = self.bucket_duration * (1 << level) as f64;
+        let peaks = &self.levels[level];
+
+        let first = (start.max(0.0) / bucket_duration).floor() as usize;
+        let last = ((end / bucket_duration).ceil() as usize).min(peaks.len());
+        let peaks = peaks.get(first..last)?;
+        if peaks.is_empty() {
+            return None;
+        }
+
+        let mut peak = peaks[0];
+        let mut sum_squares = 0.0;
+        for other in peaks {
+            peak.min = peak.min.min(other.min);
+            peak.max = peak.max.max(other.max);
+            sum_squares += other.rms * other.rms;
+        }
+        peak.rms = (sum_squares / peaks.len() as f32).sqrt();
+        Some(peak)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A waveform with one-second buckets that get louder by one each second.
+    fn ramp(buckets: usize) -> Waveform {
+        let buckets = (0..buckets)
+            .map(|i| Peak {
+                min: -(i as f32),
+                max: i as f32,
+                rms: 1.0,
+            })
+            .collect();
+        Waveform::from_buckets(buckets, 1.0)
+    }
+
+    fn assert_range(peak: Option<Peak>, min: f32, max: f32) {
+        let peak = peak.unwrap();
+        assert_eq!((peak.min, peak.max), (min, max));
+        assert!((peak.rms - 1.0).abs() < 1e-6, "{:?}", peak);
+    }
+
+    #[test]
+    fn merge() {
+        let a = Peak {
+            min: -0.5,
+            max: 0.25,
+            rms: 3.0,
+        };
+        let b = Peak {
+            min: -0.25,
+            max: 0.75,
+            rms: 4.0,
+        };
+        let merged = a.merge(&b);
+        assert_eq!((merged.min, merged.max), (-0.5, 0.75));
+        assert!((merged.rms - 12.5f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn levels_halve() {
+        let waveform = ramp(5);
+        let lengths = waveform.levels.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, vec![5, 3, 2, 1]);
+        // the odd bucket at the end is carried up as it is
+        assert_eq!(waveform.levels[1][2].max, 4.0);
+        assert_eq!(waveform.levels[3][0].max, 4.0);
+    }
+
+    #[test]
+    fn picks_the_coarsest_level_that_fits() {
+        let waveform = ramp(8);
+        // a single bucket
+        assert_range(waveform.peak_between(0.0, 1.0), 0.0, 0.0);
+        // part of a bucket still covers all of it
+        assert_range(waveform.peak_between(2.0, 3.5), -3.0, 3.0);
+        // two buckets' worth comes from the next level, which lines up on pairs
+        assert_range(waveform.peak_between(3.0, 5.0), -5.0, 5.0);
+        // four buckets' worth is a single bucket two levels up
+        assert_range(waveform.peak_between(0.0, 4.0), -3.0, 3.0);
+        // zoomed out further than there are levels
+        assert_range(waveform.peak_between(0.0, 100.0), -7.0, 7.0);
+    }
+
+    #[test]
+    fn outside_the_song() {
+        let waveform = ramp(8);
+        assert!(waveform.peak_between(-2.0, -1.0).is_none());
+        assert!(waveform.peak_between(20.0, 21.0).is_none());
+        assert!(waveform.peak_between(2.0, 2.0).is_none());
+        // the part before the song starts is left out
+        assert_range(waveform.peak_between(-0.5, 0.5), 0.0, 0.0);
+    }
+}
@@ -1,13 +1,178 @@
+use std::path::Path;
+
+use anyhow::Result;
 use ggez::graphics::Color;
-use libosu::{beatmap::Beatmap, hitobject::HitObjectKind, math::Point};
+use libosu::{
+    beatmap::Beatmap,
+    color::Color as ComboColor,
+    enums::Mode,
+    hitobject::HitObjectKind,
+    math::Point,
+    timing::{Millis, TimingPoint, TimingPointKind},
+};
 
 use crate::hitobject::HitObjectExt;
+use crate::utils;
 
 pub const STACK_DISTANCE: f64 = 3.0;
 
+/// Game modes in the order they're shown in the UI.
+pub const MODES: &[(Mode, &str)] = &[
+    (Mode::Osu, "osu!"),
+    (Mode::Taiko, "osu!taiko"),
+    (Mode::Catch, "osu!catch"),
+    (Mode::Mania, "osu!mania"),
+];
+
+/// Everything about a beatmap that's edited through the Song Setup window.
+#[derive(Clone, Debug, Default)]
+pub struct BeatmapProperties {
+    pub artist: String,
+    pub artist_unicode: String,
+    pub title: String,
+    pub title_unicode: String,
+    pub creator: String,
+    pub difficulty_name: String,
+    pub source: String,
+    /// Space-separated, like in the .osu file.
+    pub tags: String,
+
+    pub hp_drain_rate: f32,
+    pub circle_size: f32,
+    pub approach_rate: f32,
+    pub overall_difficulty: f32,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+
+    /// In milliseconds.
+    pub audio_leadin: i32,
+    /// In milliseconds.
+    pub preview_time: i32,
+
+    pub stack_leniency: f64,
+    /// Index into [`MODES`].
+    pub mode: usize,
+
+    /// RGB from 0 to 1, which is what the color pickers work with. An empty list means the
+    /// default colors are used.
+    pub combo_colors: Vec<[f32; 3]>,
+    pub slider_border: Option<[f32; 3]>,
+    pub slider_track_override: Option<[f32; 3]>,
+}
+
+impl BeatmapProperties {
+    pub fn from_beatmap(beatmap_ext: &BeatmapExt) -> Self {
+        let beatmap = &beatmap_ext.inner;
+        BeatmapProperties {
+            artist: beatmap.artist.clone(),
+            artist_unicode: beatmap.artist_unicode.clone(),
+            title: beatmap.title.clone(),
+            title_unicode: beatmap.title_unicode.clone(),
+            creator: beatmap.creator.clone(),
+            difficulty_name: beatmap.difficulty_name.clone(),
+            source: beatmap.source.clone(),
+            tags: beatmap.tags.join(" "),
+
+            hp_drain_rate: beatmap.difficulty.hp_drain_rate as f32,
+            circle_size: beatmap.difficulty.circle_size as f32,
+            approach_rate: beatmap.difficulty.approach_rate as f32,
+            overall_difficulty: beatmap.difficulty.overall_difficulty as f32,
+            slider_multiplier: beatmap.difficulty.slider_multiplier as f64,
+            slider_tick_rate: beatmap.difficulty.slider_tick_rate as f64,
+
+            audio_leadin: beatmap.audio_leadin.0,
+            preview_time: beatmap.preview_time.0,
+
+            stack_leniency: beatmap.stack_leniency as f64,
+            mode: MODES
+                .iter()
+                .position(|(mode, _)| *mode == beatmap.mode)
+                .unwrap_or(0),
+
+            combo_colors: beatmap
+                .colors
+                .iter()
+                .map(|color| rgb_to_f32([color.red, color.green, color.blue]))
+                .collect(),
+            slider_border: beatmap_ext.slider_border.map(rgb_to_f32),
+            slider_track_override: beatmap_ext.slider_track_override.map(rgb_to_f32),
+        }
+    }
+
+    pub fn apply_to(&self, beatmap_ext: &mut BeatmapExt) {
+        beatmap_ext.slider_border = self.slider_border.map(rgb_from_f32);
+        beatmap_ext.slider_track_override = self.slider_track_override.map(rgb_from_f32);
+
+        let beatmap = &mut beatmap_ext.inner;
+        beatmap.artist = self.artist.clone();
+        beatmap.artist_unicode = self.artist_unicode.clone();
+        beatmap.title = self.title.clone();
+        beatmap.title_unicode = self.title_unicode.clone();
+        beatmap.creator = self.creator.clone();
+        beatmap.difficulty_name = self.difficulty_name.clone();
+        beatmap.source = self.source.clone();
+        beatmap.tags = self.tags.split_whitespace().map(String::from).collect();
+
+        beatmap.difficulty.hp_drain_rate = self.hp_drain_rate as _;
+        beatmap.difficulty.circle_size = self.circle_size as _;
+        beatmap.difficulty.approach_rate = self.approach_rate as _;
+        beatmap.difficulty.overall_difficulty = self.overall_difficulty as _;
+        beatmap.difficulty.slider_multiplier = self.slider_multiplier as _;
+        beatmap.difficulty.slider_tick_rate = self.slider_tick_rate as _;
+
+        beatmap.audio_leadin = Millis(self.audio_leadin);
+        beatmap.preview_time = Millis(self.preview_time);
+
+        beatmap.stack_leniency = self.stack_leniency as _;
+        if let Some((mode, _)) = MODES.get(self.mode) {
+            beatmap.mode = *mode;
+        }
+
+        beatmap.colors = self
+            .combo_colors
+            .iter()
+            .map(|color| {
+                let [red, green, blue] = rgb_from_f32(*color);
+                ComboColor { red, green, blue }
+            })
+            .collect();
+    }
+}
+
+pub fn rgb_to_f32(rgb: [u8; 3]) -> [f32; 3] {
+    [
+        rgb[0] as f32 / 255.0,
+        rgb[1] as f32 / 255.0,
+        rgb[2] as f32 / 255.0,
+    ]
+}
+
+fn rgb_from_f32(rgb: [f32; 3]) -> [u8; 3] {
+    let channel = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2])]
+}
+
+/// Parses a color written like `255,128,0` in a .osu file.
+fn parse_rgb(value: &str) -> Option<[u8; 3]> {
+    let mut parts = value.split(',').map(|part| part.trim().parse::<u8>());
+    let mut rgb = [0; 3];
+    for channel in rgb.iter_mut() {
+        *channel = parts.next()?.ok()?;
+    }
+    Some(rgb)
+}
+
+const SLIDER_BORDER_KEY: &str = "SliderBorder";
+const SLIDER_TRACK_OVERRIDE_KEY: &str = "SliderTrackOverride";
+
 pub struct BeatmapExt {
     pub inner: Beatmap,
     pub hit_objects: Vec<HitObjectExt>,
+
+    /// libosu only knows about combo colors, so the slider colors from the [Colours] section are
+    /// read and written by the editor itself.
+    pub slider_border: Option<[u8; 3]>,
+    pub slider_track_override: Option<[u8; 3]>,
 }
 
 impl BeatmapExt {
@@ -19,16 +184,170 @@ impl BeatmapExt {
             .map(HitObjectExt::new)
             .collect();
 
-        BeatmapExt { inner, hit_objects }
+        BeatmapExt {
+            inner,
+            hit_objects,
+            slider_border: None,
+            slider_track_override: None,
+        }
+    }
+
+    /// Reads the slider colors out of the [Colours] section of the .osu file the beatmap was
+    /// parsed from.
+    pub fn read_slider_colors(&mut self, contents: &str) {
+        let mut in_colours = false;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_colours = line == "[Colours]";
+                continue;
+            }
+            if !in_colours {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ':');
+            let key = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value,
+                None => continue,
+            };
+            if key == SLIDER_BORDER_KEY {
+                self.slider_border = parse_rgb(value);
+            } else if key == SLIDER_TRACK_OVERRIDE_KEY {
+                self.slider_track_override = parse_rgb(value);
+            }
+        }
+    }
+
+    /// Adds the slider colors to the [Colours] section of a serialized beatmap, creating the
+    /// section if there are no combo colors.
+    fn write_slider_colors(&self, contents: &str) -> String {
+        let newline = if contents.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut extra = Vec::new();
+        if let Some([r, g, b]) = self.slider_border {
+            extra.push(format!("{}: {},{},{}", SLIDER_BORDER_KEY, r, g, b));
+        }
+        if let Some([r, g, b]) = self.slider_track_override {
+            extra.push(format!("{}: {},{},{}", SLIDER_TRACK_OVERRIDE_KEY, r, g, b));
+        }
+        if extra.is_empty() {
+            return contents.to_owned();
+        }
+
+        let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+        match lines.iter().position(|line| line.trim() == "[Colours]") {
+            Some(header) => {
+                // insert after the last line of the section that isn't blank
+                let mut end = header + 1;
+                for (idx, line) in lines.iter().enumerate().skip(header + 1) {
+                    let line = line.trim();
+                    if line.starts_with('[') {
+                        break;
+                    }
+                    if !line.is_empty() {
+                        end = idx + 1;
+                    }
+                }
+                lines.splice(end..end, extra);
+            }
+            None => {
+                let at = lines
+                    .iter()
+                    .position(|line| line.trim() == "[HitObjects]")
+                    .unwrap_or_else(|| lines.len());
+                let mut section = vec!["[Colours]".to_owned()];
+                section.extend(extra);
+                section.push(String::new());
+                lines.splice(at..at, section);
+            }
+        }
+
+        let mut result = lines.join(newline);
+        result.push_str(newline);
+        result
+    }
+
+    /// Copies the editor's view of the hit objects back into the inner beatmap, so it can be
+    /// serialized.
+    pub fn sync(&mut self) {
+        self.inner.hit_objects = self.hit_objects.iter().map(|ho| ho.inner.clone()).collect();
+    }
+
+    /// Writes the beatmap out to the given path in .osu format.
+    ///
+    /// The file is replaced atomically, so a crash halfway through never leaves a partially
+    /// written beatmap behind.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.sync();
+        let contents = self.write_slider_colors(&self.inner.to_string());
+        utils::write_atomic(path, contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Finds the uninherited timing point in effect at the given time (in seconds). Times before
+    /// the first timing point use the first one.
+    pub fn uninherited_timing_point_at(&self, time: f64) -> Option<&TimingPoint> {
+        let mut uninherited = self
+            .inner
+            .timing_points
+            .iter()
+            .filter(|tp| matches!(tp.kind, TimingPointKind::Uninherited(_)));
+        let mut result = uninherited.next();
+        for timing_point in uninherited {
+            if timing_point.time.as_seconds() > time {
+                break;
+            }
+            result = Some(timing_point);
+        }
+        result
+    }
+
+    /// Finds the inherited timing point in effect at the given time (in seconds). Uninherited
+    /// timing points reset the slider velocity, so this is None if there's an uninherited timing
+    /// point after the last inherited one.
+    pub fn inherited_timing_point_at(&self, time: f64) -> Option<&TimingPoint> {
+        self.inner
+            .timing_points
+            .iter()
+            .take_while(|tp| tp.time.as_seconds() <= time)
+            .last()
+            .filter(|tp| matches!(tp.kind, TimingPointKind::Inherited(_)))
     }
 
+    /// Finds the timing point (of either kind) in effect at the given time (in seconds).
+    pub fn timing_point_at(&self, time: f64) -> Option<&TimingPoint> {
+        self.inner
+            .timing_points
+            .iter()
+            .take_while(|tp| tp.time.as_seconds() <= time)
+            .last()
+            .or_else(|| self.inner.timing_points.first())
+    }
+
+    /// Numbers the hit objects and picks their combo colors the same way osu! does.
+    ///
+    /// The first object always starts a new combo. Spinners never start one themselves, but the
+    /// object after a spinner always does, and a color skip on a spinner carries over to it.
     pub fn compute_colors(&mut self, colors: &[Color]) {
         let mut color_idx = 0;
         let mut number = 1;
+        let mut force_new_combo = true;
+        let mut extra_skip = 0;
         for ho in self.hit_objects.iter_mut() {
-            if ho.inner.new_combo {
+            if ho.inner.kind.is_spinner() {
+                force_new_combo = true;
+                extra_skip += ho.inner.skip_color as usize;
+            } else if ho.inner.new_combo || force_new_combo {
                 number = 1;
-                color_idx = (color_idx + 1) % colors.len();
+                let skip = ho.inner.skip_color as usize + extra_skip;
+                color_idx = (color_idx + 1 + skip) % colors.len();
+                force_new_combo = false;
+                extra_skip = 0;
             }
 
             ho.number = number;
@@ -38,7 +357,12 @@ impl BeatmapExt {
     }
 
     pub fn compute_stacking(&mut self) {
-        if self.inner.stack_leniency > 0.0 {
+        // stacking is computed from scratch, since edits can break up existing stacks
+        for ho in self.hit_objects.iter_mut() {
+            ho.stacking = 0;
+        }
+
+        if self.inner.stack_leniency > 0.0 && !self.hit_objects.is_empty() {
             self.compute_stacking_inner(0, self.hit_objects.len() - 1)
         }
     }
@@ -59,7 +383,7 @@ impl BeatmapExt {
 
                     let object_n = &self.hit_objects[n];
                     if let HitObjectKind::Spinner(_) = &object_n.inner.kind {
-                        break;
+                        continue;
                     }
 
                     let end_time = self
@@ -78,8 +402,7 @@ impl BeatmapExt {
                     let object_n_pos: Point<f64> = object_n.inner.pos.to_float().unwrap();
                     if stack_base_pos.distance(object_n_pos) < STACK_DISTANCE
                         || (stack_base_obj.inner.kind.is_slider()
-                            && stack_base_obj.inner.end_pos().distance(object_n_pos)
-                                < STACK_DISTANCE)
+                            && stack_base_obj.final_pos().distance(object_n_pos) < STACK_DISTANCE)
                     {
                         stack_base_idx = n;
                         self.hit_objects[n].stacking = 0;
@@ -142,8 +465,7 @@ impl BeatmapExt {
 
                         if self.hit_objects[n].inner.kind.is_slider()
                             && self.hit_objects[n]
-                                .inner
-                                .end_pos()
+                                .final_pos()
                                 .distance(self.hit_objects[iidx].inner.pos.to_float().unwrap())
                                 < STACK_DISTANCE
                         {
@@ -152,8 +474,7 @@ impl BeatmapExt {
 
                             for j in n + 1..=i {
                                 if self.hit_objects[n]
-                                    .inner
-                                    .end_pos()
+                                    .final_pos()
                                     .distance(self.hit_objects[j].inner.pos.to_float().unwrap())
                                     < STACK_DISTANCE
                                 {
@@ -196,8 +517,7 @@ impl BeatmapExt {
                         }
 
                         if self.hit_objects[n]
-                            .inner
-                            .end_pos()
+                            .final_pos()
                             .distance(self.hit_objects[iidx].inner.pos.to_float().unwrap())
                             < STACK_DISTANCE
                         {
@@ -212,3 +532,62 @@ impl BeatmapExt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beatmap_with_slider_colors() -> BeatmapExt {
+        let mut beatmap = BeatmapExt::new(Beatmap::default());
+        beatmap.slider_border = Some([255, 128, 0]);
+        beatmap.slider_track_override = Some([10, 20, 30]);
+        beatmap
+    }
+
+    #[test]
+    fn slider_colors_round_trip() {
+        let original = "[Colours]\nCombo1 : 255,0,0\nSliderBorder: 255,128,0\nSliderTrackOverride: 10,20,30\n\n[HitObjects]\n";
+        let mut beatmap = BeatmapExt::new(Beatmap::default());
+        beatmap.read_slider_colors(original);
+        assert_eq!(beatmap.slider_border, Some([255, 128, 0]));
+        assert_eq!(beatmap.slider_track_override, Some([10, 20, 30]));
+
+        // libosu writes the section without the slider colors
+        let written = beatmap.write_slider_colors("[Colours]\nCombo1 : 255,0,0\n\n[HitObjects]\n");
+        assert_eq!(written, original);
+
+        let mut reread = BeatmapExt::new(Beatmap::default());
+        reread.read_slider_colors(&written);
+        assert_eq!(reread.slider_border, beatmap.slider_border);
+        assert_eq!(reread.slider_track_override, beatmap.slider_track_override);
+    }
+
+    #[test]
+    fn slider_colors_add_section_before_hit_objects() {
+        let beatmap = beatmap_with_slider_colors();
+        let written = beatmap.write_slider_colors("[Events]\n\n[HitObjects]\n64,64,0,1,0\n");
+        assert_eq!(
+            written,
+            "[Events]\n\n[Colours]\nSliderBorder: 255,128,0\nSliderTrackOverride: 10,20,30\n\n[HitObjects]\n64,64,0,1,0\n"
+        );
+    }
+
+    #[test]
+    fn slider_colors_keep_crlf() {
+        let beatmap = beatmap_with_slider_colors();
+        let written =
+            beatmap.write_slider_colors("[Colours]\r\nCombo1 : 255,0,0\r\n\r\n[HitObjects]\r\n");
+        assert_eq!(
+            written,
+            "[Colours]\r\nCombo1 : 255,0,0\r\nSliderBorder: 255,128,0\r\nSliderTrackOverride: 10,20,30\r\n\r\n[HitObjects]\r\n"
+        );
+        assert!(!written.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn slider_colors_unchanged_without_any() {
+        let beatmap = BeatmapExt::new(Beatmap::default());
+        let contents = "[Colours]\r\nCombo1 : 255,0,0\r\n";
+        assert_eq!(beatmap.write_slider_colors(contents), contents);
+    }
+}
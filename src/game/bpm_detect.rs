@@ -0,0 +1,223 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use imgui::{Ui, Window};
+use libosu::{
+    hitsounds::SampleSet,
+    timing::{Millis, TimingPoint, TimingPointKind, UninheritedTimingInfo},
+};
+
+use crate::audio::TempoEstimate;
+use crate::timestamp::format_time;
+
+use super::{ui::UiAction, Game};
+
+/// Below this, the suggestion comes with a warning to check it by ear.
+const LOW_CONFIDENCE: f64 = 0.3;
+
+/// A tempo detection that was started on part of the song.
+pub struct TempoDetection {
+    /// The red line that the detection is for, if there was one.
+    timing_point: Option<Millis>,
+    result: Arc<Mutex<Option<Result<TempoEstimate, String>>>>,
+}
+
+/// How the tempo detection is going, for the UI.
+#[derive(Debug)]
+pub enum TempoStatus {
+    Idle,
+    Running,
+    Done(TempoEstimate),
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct BpmDetectionState {
+    pub opened: bool,
+}
+
+pub(super) fn draw_bpm_detection(
+    ui: &Ui,
+    state: &mut BpmDetectionState,
+    section: Option<(f64, f64)>,
+    status: &TempoStatus,
+    actions: &mut Vec<UiAction>,
+) {
+    if !state.opened {
+        return;
+    }
+
+    let mut opened = true;
+    Window::new("Detect BPM")
+        .opened(&mut opened)
+        .always_auto_resize(true)
+        .build(ui, || {
+            let (start, end) = match section {
+                Some(section) => section,
+                None => {
+                    ui.text_disabled("Open a beatmap to detect its timing.");
+                    return;
+                }
+            };
+            ui.text(format!(
+                "Section: {} to {}",
+                format_time(Millis::from_seconds(start)),
+                format_time(Millis::from_seconds(end))
+            ));
+
+            let running = matches!(status, TempoStatus::Running);
+            if ui.button("Analyze") && !running {
+                actions.push(UiAction::DetectTempo);
+            }
+
+            ui.separator();
+            match status {
+                TempoStatus::Idle => {
+                    ui.text_disabled("Listens to this section and suggests a red line for it.")
+                }
+                TempoStatus::Running => ui.text("Analyzing..."),
+                TempoStatus::Failed(err) => ui.text_colored([1.0, 0.4, 0.4, 1.0], err),
+                TempoStatus::Done(estimate) => {
+                    ui.text(format!("BPM: {:.2}", estimate.bpm));
+                    ui.text(format!(
+                        "Offset: {}",
+                        format_time(Millis::from_seconds(estimate.offset))
+                    ));
+                    ui.text(format!("Meter: {}/4", estimate.meter));
+                    ui.text(format!("Confidence: {:.0}%", estimate.confidence * 100.0));
+                    if estimate.confidence < LOW_CONFIDENCE {
+                        ui.text_colored([1.0, 0.6, 0.2, 1.0], "Low confidence, check this by ear.");
+                    }
+                    if ui.button("Accept") {
+                        actions.push(UiAction::AcceptTempo(*estimate));
+                    }
+                }
+            }
+        });
+    state.opened = opened;
+}
+
+impl Game {
+    /// The section of the song that the current red line covers, in seconds, along with the time
+    /// of that red line. Before the first red line, the section starts at the beginning of the
+    /// song.
+    fn current_section(&self) -> Option<(f64, f64, Option<Millis>)> {
+        let song = self.song.as_ref()?;
+        let time = song.position().ok()?;
+        let length = song.length().ok()?;
+
+        let red_lines = self
+            .beatmap
+            .inner
+            .timing_points
+            .iter()
+            .filter(|tp| matches!(tp.kind, TimingPointKind::Uninherited(_)))
+            .map(|tp| tp.time)
+            .collect::<Vec<_>>();
+        let current = red_lines
+            .iter()
+            .rev()
+            .find(|red_line| red_line.as_seconds() <= time)
+            .cloned();
+        let start = current.map_or(0.0, |red_line| red_line.as_seconds().max(0.0));
+        let end = red_lines
+            .iter()
+            .find(|red_line| red_line.as_seconds() > time)
+            .map_or(length, |red_line| red_line.as_seconds());
+        Some((start, end, current))
+    }
+
+    pub(super) fn tempo_section(&self) -> Option<(f64, f64)> {
+        self.current_section().map(|(start, end, _)| (start, end))
+    }
+
+    pub(super) fn tempo_status(&self) -> TempoStatus {
+        let detection = match &self.tempo_detection {
+            Some(detection) => detection,
+            None => return TempoStatus::Idle,
+        };
+        let result = detection.result.lock().unwrap();
+        match &*result {
+            None => TempoStatus::Running,
+            Some(Ok(estimate)) => TempoStatus::Done(*estimate),
+            Some(Err(err)) => TempoStatus::Failed(err.clone()),
+        }
+    }
+
+    /// Starts guessing the timing of the current section in the background.
+    pub(super) fn detect_tempo(&mut self) {
+        let (start, end, timing_point) = match self.current_section() {
+            Some(section) => section,
+            None => return,
+        };
+        if let Some(song) = &self.song {
+            self.tempo_detection = Some(TempoDetection {
+                timing_point,
+                result: song.detect_tempo(start, end),
+            });
+        }
+    }
+
+    /// Puts the suggested timing into the beatmap. The red line it was detected for moves back to
+    /// the closest detected downbeat at or before its time, so the objects after it stay in its
+    /// section, and gets the new BPM and meter. If there wasn't one, a new red line is added at
+    /// the detected offset.
+    pub(super) fn accept_tempo(&mut self, estimate: TempoEstimate) {
+        let replaced = self
+            .tempo_detection
+            .as_ref()
+            .and_then(|detection| detection.timing_point);
+        let info = UninheritedTimingInfo {
+            mpb: 60_000.0 / estimate.bpm,
+            meter: estimate.meter as _,
+        };
+        let time = match replaced {
+            Some(replaced) => {
+                let seconds = estimate.downbeat_at_or_before(replaced.as_seconds());
+                let downbeat = Millis::from_seconds(seconds);
+                // moving it back past the red line before it would swap their sections around
+                let previous = self
+                    .beatmap
+                    .inner
+                    .timing_points
+                    .iter()
+                    .filter(|tp| matches!(tp.kind, TimingPointKind::Uninherited(_)))
+                    .map(|tp| tp.time)
+                    .filter(|time| *time < replaced)
+                    .max();
+                match previous {
+                    Some(previous) if previous >= downbeat => {
+                        warn!(
+                            "not moving the red line at {}ms back past the one at {}ms",
+                            replaced.0, previous.0
+                        );
+                        replaced
+                    }
+                    _ => downbeat,
+                }
+            }
+            None => Millis::from_seconds(estimate.offset),
+        };
+
+        self.edit_timing_points("Detect timing", |timing_points| {
+            let existing = timing_points.iter_mut().find(|tp| {
+                Some(tp.time) == replaced && matches!(tp.kind, TimingPointKind::Uninherited(_))
+            });
+            match existing {
+                Some(timing_point) => {
+                    timing_point.time = time;
+                    timing_point.kind = TimingPointKind::Uninherited(info);
+                }
+                None => timing_points.push(TimingPoint {
+                    kind: TimingPointKind::Uninherited(info),
+                    time,
+                    sample_set: SampleSet::Normal,
+                    sample_index: 0,
+                    volume: 100,
+                    kiai: false,
+                }),
+            }
+        });
+        self.tempo_detection = None;
+    }
+}
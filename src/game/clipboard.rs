@@ -0,0 +1,253 @@
+use anyhow::{anyhow, Result};
+use copypasta::{ClipboardContext, ClipboardProvider};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    timing::Millis,
+};
+
+use crate::history::{Change, Command};
+
+use super::Game;
+
+/// Writes the objects as `[HitObjects]` lines, the same way they'd be written in a .osu file.
+fn objects_to_text(objects: &[&HitObject]) -> String {
+    objects
+        .iter()
+        .map(|ho| ho.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads hit objects out of `[HitObjects]` lines in time order, skipping any lines that aren't
+/// hit objects (such as a section header copied along with them).
+fn objects_from_text(text: &str) -> Vec<HitObject> {
+    let mut objects = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| match line.parse::<HitObject>() {
+            Ok(ho) => Some(ho),
+            Err(err) => {
+                debug!("not a hit object: {:?} ({:?})", line, err);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    objects.sort_by_key(|ho| ho.start_time);
+    objects
+}
+
+/// Puts the objects on the system clipboard.
+fn copy_to_clipboard(objects: &[&HitObject]) -> Result<()> {
+    set_clipboard_text(objects_to_text(objects))
+}
+
+pub(super) fn set_clipboard_text(text: String) -> Result<()> {
+    let mut clipboard = ClipboardContext::new().map_err(|err| anyhow!("{}", err))?;
+    clipboard
+        .set_contents(text)
+        .map_err(|err| anyhow!("{}", err))?;
+    Ok(())
+}
+
+/// Reads hit objects off of the system clipboard.
+fn paste_from_clipboard() -> Result<Vec<HitObject>> {
+    let mut clipboard = ClipboardContext::new().map_err(|err| anyhow!("{}", err))?;
+    let text = clipboard.get_contents().map_err(|err| anyhow!("{}", err))?;
+    Ok(objects_from_text(&text))
+}
+
+impl Game {
+    /// Copies the selected objects in the order they're in the beatmap. Returns whether they were
+    /// copied.
+    pub(super) fn copy_selection(&self) -> bool {
+        let mut indices = self.selected_objects.clone();
+        indices.sort_unstable();
+        let objects = indices
+            .iter()
+            .map(|idx| &self.beatmap.hit_objects[*idx].inner)
+            .collect::<Vec<_>>();
+        if objects.is_empty() {
+            return false;
+        }
+
+        match copy_to_clipboard(&objects) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("failed to copy to the clipboard: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Copies the selected objects and deletes them, unless they couldn't be copied.
+    pub(super) fn cut_selection(&mut self) {
+        if self.copy_selection() {
+            self.delete_selection();
+        }
+    }
+
+    /// Pastes the objects on the clipboard so the first one is at the current time, keeping the
+    /// timing between them. Every object is snapped to the beat, and any that would land on the
+    /// same time as another object are left out.
+    pub(super) fn paste(&mut self) {
+        let time = match &self.song {
+            Some(song) => match song.position() {
+                Ok(time) => time,
+                Err(_) => return,
+            },
+            None => return,
+        };
+        let objects = match paste_from_clipboard() {
+            Ok(objects) => objects,
+            Err(err) => {
+                error!("failed to paste from the clipboard: {}", err);
+                return;
+            }
+        };
+        let first_time = match objects.first() {
+            Some(ho) => ho.start_time,
+            None => return,
+        };
+
+        let start = self.snap_time(time);
+        let mut pasted = Vec::<HitObject>::new();
+        let mut skipped = 0;
+        for mut ho in objects {
+            let offset = (ho.start_time.0 - first_time.0) as f64 / 1000.0;
+            let new_time = self.snap_time(start.as_seconds() + offset);
+            let occupied = self
+                .beatmap
+                .hit_objects
+                .binary_search_by_key(&new_time, |ho| ho.inner.start_time)
+                .is_ok()
+                || pasted.iter().any(|ho| ho.start_time == new_time);
+            if occupied {
+                skipped += 1;
+                continue;
+            }
+
+            let delta = new_time.0 - ho.start_time.0;
+            ho.start_time = new_time;
+            if let HitObjectKind::Spinner(info) = &mut ho.kind {
+                info.end_time = Millis(info.end_time.0 + delta);
+            }
+            pasted.push(ho);
+        }
+        if skipped > 0 {
+            warn!(
+                "left out {} pasted objects that would've been on top of other objects",
+                skipped
+            );
+        }
+
+        // the pasted objects are in order, so each one goes after the ones before it
+        let mut command = Command::new("Paste");
+        let mut indices = Vec::new();
+        for (i, ho) in pasted.into_iter().enumerate() {
+            let index = i + self
+                .beatmap
+                .hit_objects
+                .iter()
+                .take_while(|existing| existing.inner.start_time < ho.start_time)
+                .count();
+            indices.push(index);
+            command.push(Change::InsertHitObject { index, object: ho });
+        }
+        self.execute(command);
+        self.selected_objects = indices;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libosu::{
+        hitobject::{SliderInfo, SliderSplineKind, SpinnerInfo},
+        hitsounds::{Additions, SampleInfo},
+        math::Point,
+    };
+
+    use super::*;
+
+    fn object(time: i32, kind: HitObjectKind) -> HitObject {
+        HitObject {
+            start_time: Millis(time),
+            pos: Point::new(256, 192),
+            kind,
+            new_combo: false,
+            skip_color: 0,
+            additions: Additions::empty(),
+            sample_info: SampleInfo::default(),
+        }
+    }
+
+    fn objects() -> Vec<HitObject> {
+        vec![
+            object(1000, HitObjectKind::Circle),
+            object(
+                1500,
+                HitObjectKind::Slider(SliderInfo {
+                    kind: SliderSplineKind::Bezier,
+                    control_points: vec![Point::new(300, 192), Point::new(300, 250)],
+                    num_repeats: 2,
+                    pixel_length: 120.0,
+                    edge_additions: vec![],
+                    edge_samplesets: vec![],
+                }),
+            ),
+            object(
+                2500,
+                HitObjectKind::Spinner(SpinnerInfo {
+                    end_time: Millis(4000),
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let objects = objects();
+        let text = objects_to_text(&objects.iter().collect::<Vec<_>>());
+        assert_eq!(text.lines().count(), 3);
+
+        let pasted = objects_from_text(&text);
+        assert_eq!(objects_to_text(&pasted.iter().collect::<Vec<_>>()), text);
+        assert!(matches!(pasted[0].kind, HitObjectKind::Circle));
+        match &pasted[1].kind {
+            HitObjectKind::Slider(info) => {
+                assert_eq!(info.control_points.len(), 2);
+                assert_eq!(info.num_repeats, 2);
+                assert_eq!(info.pixel_length, 120.0);
+            }
+            kind => panic!("expected a slider, got {:?}", kind),
+        }
+        match &pasted[2].kind {
+            HitObjectKind::Spinner(info) => assert_eq!(info.end_time.0, 4000),
+            kind => panic!("expected a spinner, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn skips_lines_that_arent_objects() {
+        let objects = objects();
+        let lines = objects.iter().map(|ho| ho.to_string()).collect::<Vec<_>>();
+        let text = format!(
+            "[HitObjects]\r\n{}\r\n\r\n  {}  \r\nnot,an,object\r\n",
+            lines[0], lines[2]
+        );
+
+        let pasted = objects_from_text(&text);
+        let times = pasted.iter().map(|ho| ho.start_time.0).collect::<Vec<_>>();
+        assert_eq!(times, vec![1000, 2500]);
+    }
+
+    #[test]
+    fn sorts_by_time() {
+        let objects = objects();
+        let text = objects_to_text(&objects.iter().rev().collect::<Vec<_>>());
+
+        let pasted = objects_from_text(&text);
+        let times = pasted.iter().map(|ho| ho.start_time.0).collect::<Vec<_>>();
+        assert_eq!(times, vec![1000, 1500, 2500]);
+    }
+}
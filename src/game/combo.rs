@@ -0,0 +1,57 @@
+use super::Game;
+
+/// The color skip is stored in 3 bits of the object type.
+pub const MAX_COLOR_SKIP: u32 = 7;
+
+impl Game {
+    /// Toggles new combo on the selected objects. If only some of them start a new combo, all of
+    /// them are made to.
+    pub(super) fn toggle_new_combo(&mut self) {
+        if self.selected_objects.is_empty() {
+            return;
+        }
+
+        let new_combo = !self
+            .selected_objects
+            .iter()
+            .all(|idx| self.beatmap.hit_objects[*idx].inner.new_combo);
+        let name = if new_combo {
+            "New combo"
+        } else {
+            "Remove new combo"
+        };
+        self.edit_selection(name, |ho| ho.new_combo = new_combo);
+    }
+
+    /// Sets how many combo colors the selected objects skip. The skip only counts on objects
+    /// that start a new combo, so setting one turns new combo on for them. Clearing it leaves new
+    /// combo as it was.
+    pub(super) fn set_color_skip(&mut self, skip: u32) {
+        if self.selected_objects.is_empty() {
+            return;
+        }
+
+        let skip = skip.min(MAX_COLOR_SKIP);
+        self.edit_selection("Change color skip", |ho| {
+            if skip > 0 {
+                ho.new_combo = true;
+            }
+            ho.skip_color = skip as _;
+        });
+    }
+
+    /// The color skip shared by all of the selected objects, if there's a selection and they all
+    /// have the same one.
+    pub(super) fn selection_color_skip(&self) -> Option<u32> {
+        let mut skips = self
+            .selected_objects
+            .iter()
+            .map(|idx| self.beatmap.hit_objects[*idx].inner.skip_color as u32);
+        let first = skips.next()?;
+        if skips.all(|skip| skip == first) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+}
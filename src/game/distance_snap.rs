@@ -0,0 +1,161 @@
+use anyhow::Result;
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, FilterMode, Mesh, Text},
+    Context,
+};
+use libosu::{
+    math::Point,
+    timing::{Millis, TimingPointKind},
+};
+
+use crate::hitobject::HitObjectExt;
+use crate::utils::rect_contains;
+
+use super::select::osu_to_screen;
+use super::{Game, Tool, PLAYFIELD_BOUNDS};
+
+/// The smallest and largest distance spacing multipliers, like in osu!.
+pub const DISTANCE_SPACING_RANGE: (f64, f64) = (0.1, 6.0);
+
+impl Game {
+    /// The object that comes right before the given time, which new objects are spaced from.
+    fn previous_object(&self, time: Millis) -> Option<&HitObjectExt> {
+        self.beatmap
+            .hit_objects
+            .iter()
+            .take_while(|ho| ho.inner.start_time < time)
+            .filter(|ho| !ho.inner.kind.is_spinner())
+            .last()
+    }
+
+    /// How far apart (in osu!pixels) two objects the given time apart should be at a distance
+    /// spacing of 1x, going by the slider velocity at that time.
+    fn expected_distance(&self, gap: f64, time: Millis) -> Option<f64> {
+        let mpb = match self.beatmap.uninherited_timing_point_at(time.as_seconds()) {
+            Some(timing_point) => match &timing_point.kind {
+                TimingPointKind::Uninherited(info) if info.mpb > 0.0 => info.mpb,
+                _ => return None,
+            },
+            None => return None,
+        };
+        let slider_velocity = self.beatmap.inner.get_slider_velocity_at_time(time);
+        let slider_multiplier = self.beatmap.inner.difficulty.slider_multiplier;
+        let beats = gap * 1000.0 / mpb;
+        Some(beats * 100.0 * slider_multiplier * slider_velocity)
+    }
+
+    /// The center and radius (in osu!pixels) of the ring that an object placed at the given time
+    /// gets snapped onto, if distance snap is on.
+    fn distance_snap_ring(&self, time: Millis) -> Option<(Point<f64>, f64)> {
+        if !self.distance_snap {
+            return None;
+        }
+
+        let previous = self.previous_object(time)?;
+        let end_time = self.beatmap.inner.get_hitobject_end_time(&previous.inner)?;
+        let gap = time.as_seconds() - end_time;
+        if gap <= 0.0 {
+            return None;
+        }
+
+        let distance = self.expected_distance(gap, time)?;
+        Some((
+            previous.final_pos(),
+            distance * self.beatmap.inner.distance_spacing,
+        ))
+    }
+
+    /// Moves a position where an object is being placed onto the distance snap ring, keeping the
+    /// direction from the previous object.
+    pub(super) fn distance_snapped(&self, pos: Point<i32>, time: Millis) -> Point<i32> {
+        let (center, radius) = match self.distance_snap_ring(time) {
+            Some(ring) => ring,
+            None => return pos,
+        };
+
+        let (dx, dy) = (pos.x as f64 - center.x, pos.y as f64 - center.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        let (dx, dy) = if length > 0.0 {
+            (dx / length, dy / length)
+        } else {
+            (1.0, 0.0)
+        };
+        let x = (center.x + dx * radius).round().max(0.0).min(512.0);
+        let y = (center.y + dy * radius).round().max(0.0).min(384.0);
+        Point::new(x as i32, y as i32)
+    }
+
+    pub(super) fn set_distance_snap(&mut self, enabled: bool) {
+        self.distance_snap = enabled;
+    }
+
+    pub(super) fn set_distance_spacing(&mut self, spacing: f64) {
+        let (min, max) = DISTANCE_SPACING_RANGE;
+        self.beatmap.inner.distance_spacing = spacing.max(min).min(max) as _;
+    }
+
+    /// Draws the ring that the object being placed snaps onto.
+    pub(super) fn draw_distance_snap(&self, ctx: &mut Context, time: Millis) -> Result<()> {
+        let placing = match self.tool {
+            Tool::Circle => true,
+            Tool::Slider => self.partial_slider_state.is_none(),
+            _ => false,
+        };
+        if !placing {
+            return Ok(());
+        }
+
+        let (center, radius) = match self.distance_snap_ring(time) {
+            Some(ring) => ring,
+            None => return Ok(()),
+        };
+        let center = osu_to_screen(center.x as f32, center.y as f32);
+        let radius = radius as f32 * PLAYFIELD_BOUNDS.w / 512.0;
+        if radius < 1.0 {
+            return Ok(());
+        }
+        let ring = Mesh::new_circle(
+            ctx,
+            DrawMode::stroke(1.0),
+            center,
+            radius,
+            0.5,
+            Color::new(1.0, 1.0, 1.0, 0.3),
+        )?;
+        graphics::draw(ctx, &ring, DrawParam::default())?;
+        Ok(())
+    }
+
+    /// Shows how far the hovered object is from the one before it, compared to the distance at
+    /// 1x spacing.
+    pub(super) fn draw_spacing_ratio(&self, ctx: &mut Context, time: f64) -> Result<()> {
+        let (mx, my) = self.mouse_pos;
+        if !matches!(self.tool, Tool::Select) || !rect_contains(&PLAYFIELD_BOUNDS, mx, my) {
+            return Ok(());
+        }
+
+        let ho = match self.hitobject_at(mx, my, time) {
+            Some(idx) => &self.beatmap.hit_objects[idx].inner,
+            None => return Ok(()),
+        };
+        let previous = match self.previous_object(ho.start_time) {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+        let end_time = match self.beatmap.inner.get_hitobject_end_time(&previous.inner) {
+            Some(end_time) => end_time,
+            None => return Ok(()),
+        };
+        let expected =
+            match self.expected_distance(ho.start_time.as_seconds() - end_time, ho.start_time) {
+                Some(expected) if expected > 0.0 => expected,
+                _ => return Ok(()),
+            };
+
+        let distance = previous.final_pos().distance(ho.pos.to_float().unwrap());
+        let text = Text::new(format!("{:.2}x", distance / expected));
+        graphics::queue_text(ctx, &text, [mx + 16.0, my + 16.0], Some(Color::WHITE));
+        graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)?;
+        Ok(())
+    }
+}
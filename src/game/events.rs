@@ -2,11 +2,14 @@ use ggez::{
     event::{EventHandler, KeyCode, KeyMods, MouseButton},
     Context, GameError, GameResult,
 };
-use libosu::timing::{TimingPoint, TimingPointKind};
+use libosu::{
+    hitsounds::Additions,
+    timing::{TimingPoint, TimingPointKind},
+};
 
 use crate::utils::rect_contains;
 
-use super::{Game, Tool};
+use super::{timeline, ui::UiAction, Game, Tool, PLAYFIELD_BOUNDS};
 
 impl EventHandler for Game {
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
@@ -27,12 +30,16 @@ impl EventHandler for Game {
             return Ok(());
         }
 
+        if let Some(super::timeline_edit::TimelineDrag::Scrub(_)) = self.timeline_drag {
+            self.scrub_timeline(x).map_err(audio_error)?;
+        }
+
         if self.seeker_drag {
             use super::seeker::BOUNDS;
             let jump_percent = (x - BOUNDS.x) / BOUNDS.w;
             if let Some(song) = &self.song {
-                let pos = jump_percent as f64 * song.length().unwrap();
-                song.set_position(pos);
+                let pos = jump_percent as f64 * song.length().map_err(audio_error)?;
+                song.set_position(pos).map_err(audio_error)?;
             }
         }
         Ok(())
@@ -59,8 +66,24 @@ impl EventHandler for Game {
                     self.seeker_drag = true;
                     let jump_percent = (x - BOUNDS.x) / BOUNDS.w;
                     if let Some(song) = &self.song {
-                        let pos = jump_percent as f64 * song.length().unwrap();
-                        song.set_position(pos);
+                        let pos = jump_percent as f64 * song.length().map_err(audio_error)?;
+                        song.set_position(pos).map_err(audio_error)?;
+                    }
+                } else if rect_contains(&timeline::BOUNDS, x, y) {
+                    if let Some(song) = &self.song {
+                        let time = song.position().map_err(audio_error)?;
+                        if !self.begin_repeat_drag(x, y, time) {
+                            self.begin_timeline_drag(x, y, time);
+                        }
+                    }
+                } else if matches!(self.tool, Tool::Select)
+                    && rect_contains(&PLAYFIELD_BOUNDS, x, y)
+                {
+                    if !self.begin_anchor_drag(x, y) {
+                        if let Some(song) = &self.song {
+                            let time = song.position().map_err(audio_error)?;
+                            self.begin_selection_drag(x, y, time);
+                        }
                     }
                 }
                 self.left_drag_start = Some((x, y));
@@ -93,11 +116,18 @@ impl EventHandler for Game {
                 if let Some((px, py)) = self.left_drag_start {
                     if px == x && py == y {
                         self.handle_click(MouseButton::Left, x, y).unwrap();
+                    } else {
+                        self.handle_drag(MouseButton::Left, (px, py), (x, y))
+                            .map_err(audio_error)?;
                     }
                 } else {
                     self.handle_click(MouseButton::Left, x, y).unwrap();
                 }
                 self.left_drag_start = None;
+                self.selection_drag_anchor = None;
+                self.anchor_drag = None;
+                self.repeat_drag = None;
+                self.timeline_drag = None;
             }
             MouseButton::Right => {
                 if let Some((px, py)) = self.right_drag_start {
@@ -120,19 +150,29 @@ impl EventHandler for Game {
             return Ok(());
         }
 
-        self.seek_by_steps(-y as i32);
+        let ctrl =
+            self.keymap.contains(&KeyCode::LControl) || self.keymap.contains(&KeyCode::RControl);
+        let alt = self.keymap.contains(&KeyCode::LAlt) || self.keymap.contains(&KeyCode::RAlt);
+        if ctrl {
+            self.zoom_timeline(y);
+        } else if alt {
+            self.step_beat_divisor(y.signum() as i32);
+        } else {
+            self.seek_by_steps(-y as i32).map_err(audio_error)?;
+        }
         Ok(())
     }
 
     fn key_up_event(&mut self, _: &mut Context, keycode: KeyCode, keymods: KeyMods) -> GameResult {
         use KeyCode::*;
         self.imgui.update_key_up(keycode, keymods);
+        self.keymap.remove(&keycode);
         if self.imgui.want_capture_keyboard() {
             return Ok(());
         }
 
         match keycode {
-            Space => self.toggle_playing(),
+            Space => self.toggle_playing().map_err(audio_error)?,
             Colon => {}
             G => {
                 self.toggle_grid();
@@ -145,7 +185,7 @@ impl EventHandler for Game {
 
     fn key_down_event(
         &mut self,
-        _: &mut Context,
+        ctx: &mut Context,
         keycode: KeyCode,
         keymods: KeyMods,
         _: bool,
@@ -161,15 +201,38 @@ impl EventHandler for Game {
             Key1 => self.switch_tool_to(Tool::Select),
             Key2 => self.switch_tool_to(Tool::Circle),
             Key3 => self.switch_tool_to(Tool::Slider),
+            Key4 => self.switch_tool_to(Tool::Spinner),
+
+            S if keymods.contains(KeyMods::CTRL) => {
+                self.handle_ui_action(ctx, UiAction::Save).unwrap();
+            }
+            L if keymods.contains(KeyMods::CTRL) => {
+                self.handle_ui_action(ctx, UiAction::Revert).unwrap();
+            }
+            Z if keymods.contains(KeyMods::CTRL | KeyMods::SHIFT) => self.redo(),
+            Z if keymods.contains(KeyMods::CTRL) => self.undo(),
+            Y if keymods.contains(KeyMods::CTRL) => self.redo(),
+            C if keymods.contains(KeyMods::CTRL | KeyMods::SHIFT) => self.copy_timestamp(),
+            C if keymods.contains(KeyMods::CTRL) => {
+                self.copy_selection();
+            }
+            X if keymods.contains(KeyMods::CTRL) => self.cut_selection(),
+            V if keymods.contains(KeyMods::CTRL) => self.paste(),
+            Delete => self.delete_selection(),
+            Q => self.toggle_new_combo(),
+            W => self.toggle_addition(Additions::WHISTLE),
+            E => self.toggle_addition(Additions::FINISH),
+            R => self.toggle_addition(Additions::CLAP),
 
             Left => {
                 if let Some(TimingPoint {
-                    kind: TimingPointKind::Uninherited(info),
+                    kind: TimingPointKind::Uninherited(_),
                     ..
                 }) = &self.current_uninherited_timing_point
                 {
+                    // shift skips a whole beat
                     let steps = -if keymods.contains(KeyMods::SHIFT) {
-                        info.meter as i32
+                        self.beat_divisor() as i32
                     } else {
                         1
                     };
@@ -178,12 +241,13 @@ impl EventHandler for Game {
             }
             Right => {
                 if let Some(TimingPoint {
-                    kind: TimingPointKind::Uninherited(info),
+                    kind: TimingPointKind::Uninherited(_),
                     ..
                 }) = &self.current_uninherited_timing_point
                 {
+                    // shift skips a whole beat
                     let steps = if keymods.contains(KeyMods::SHIFT) {
-                        info.meter as i32
+                        self.beat_divisor() as i32
                     } else {
                         1
                     };
@@ -203,3 +267,7 @@ impl EventHandler for Game {
         Ok(())
     }
 }
+
+fn audio_error(err: anyhow::Error) -> GameError {
+    GameError::AudioError(err.to_string())
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use imgui::{Ui, Window};
+
+use crate::timestamp::Timestamp;
+
+use super::clipboard::set_clipboard_text;
+use super::{ui::UiAction, Game};
+
+#[derive(Debug, Default)]
+pub struct GoToState {
+    pub opened: bool,
+    input: String,
+    error: Option<String>,
+}
+
+pub(super) fn draw_go_to_timestamp(ui: &Ui, state: &mut GoToState, actions: &mut Vec<UiAction>) {
+    if !state.opened {
+        return;
+    }
+
+    let mut opened = true;
+    Window::new("Go to Timestamp")
+        .opened(&mut opened)
+        .always_auto_resize(true)
+        .build(ui, || {
+            let entered = ui
+                .input_text("##timestamp", &mut state.input)
+                .hint("01:23:456 (1,2,3) - ")
+                .enter_returns_true(true)
+                .build();
+            ui.same_line();
+            if ui.button("Go") || entered {
+                match state.input.parse::<Timestamp>() {
+                    Ok(timestamp) => {
+                        state.error = None;
+                        actions.push(UiAction::GoToTimestamp(timestamp));
+                    }
+                    Err(err) => state.error = Some(err.to_string()),
+                }
+            }
+            if let Some(error) = &state.error {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+            }
+        });
+    state.opened = opened;
+}
+
+impl Game {
+    /// Seeks to the timestamp and selects the objects it names, which are the ones with those
+    /// combo numbers, in order, starting from the timestamp.
+    pub fn go_to_timestamp(&mut self, timestamp: &Timestamp) -> Result<()> {
+        self.jump_to_time(timestamp.time.as_seconds())?;
+
+        let mut selected = Vec::new();
+        let mut objects = self
+            .beatmap
+            .hit_objects
+            .iter()
+            .enumerate()
+            .skip_while(|(_, ho)| ho.inner.start_time < timestamp.time);
+        for number in timestamp.combo_numbers.iter() {
+            match objects.find(|(_, ho)| ho.number == *number) {
+                Some((idx, _)) => selected.push(idx),
+                None => {
+                    warn!("couldn't find object {} after {}", number, timestamp);
+                    break;
+                }
+            }
+        }
+        if !timestamp.combo_numbers.is_empty() {
+            self.selected_objects = selected;
+        }
+        Ok(())
+    }
+
+    /// The timestamp that points to the selected objects.
+    pub(super) fn selection_timestamp(&self) -> Option<Timestamp> {
+        let mut selected = self.selected_objects.clone();
+        selected.sort_unstable();
+        let first = *selected.first()?;
+        Some(Timestamp {
+            time: self.beatmap.hit_objects[first].inner.start_time,
+            combo_numbers: selected
+                .iter()
+                .map(|idx| self.beatmap.hit_objects[*idx].number)
+                .collect(),
+        })
+    }
+
+    pub(super) fn copy_timestamp(&self) {
+        let timestamp = match self.selection_timestamp() {
+            Some(timestamp) => timestamp,
+            None => return,
+        };
+        if let Err(err) = set_clipboard_text(timestamp.to_string()) {
+            error!("failed to copy the timestamp: {}", err);
+        }
+    }
+}
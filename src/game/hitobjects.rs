@@ -3,17 +3,19 @@ use ggez::{
     graphics::{Color, DrawParam},
     Context,
 };
-use libosu::prelude::*;
+use libosu::{math::Point, prelude::*};
 
 use crate::{beatmap::STACK_DISTANCE, hitobject::HitObjectExt};
 
-use super::{Game, PLAYFIELD_BOUNDS};
+use super::hitsound_edit::{draw_addition_markers, edge_additions};
+use super::{spinner_screen_pos, Game, SliderCacheKey, PLAYFIELD_BOUNDS, SPINNER_SIZE};
 
 pub struct DrawInfo<'a> {
     hit_object: &'a HitObjectExt,
     fade_opacity: f64,
     end_time: f64,
     color: Color,
+    is_selected: bool,
     /// Whether or not the circle (slider head for sliders) should appear to have already
     /// been hit in the editor after the object's time has already come.
     circle_is_hit: bool,
@@ -44,12 +46,13 @@ impl Game {
         // TODO: tighten this loop even more by binary searching for the start of the timeline and
         // playfield hitobjects rather than looping through the entire beatmap, better yet, just
         // keeping track of the old index will probably be much faster
-        for ho in self.beatmap.hit_objects.iter().rev() {
+        for (idx, ho) in self.beatmap.hit_objects.iter().enumerate().rev() {
             let ho_time = ho.inner.start_time.as_seconds();
             let color = self.combo_colors[ho.color_idx];
 
             // draw in timeline
-            self.draw_hitobject_to_timeline(ctx, current_time, ho)?;
+            let selected = self.selected_objects.contains(&idx);
+            self.draw_hitobject_to_timeline(ctx, current_time, ho, selected)?;
 
             // draw hitobject in playfield
             let end_time;
@@ -89,6 +92,7 @@ impl Game {
                     fade_opacity,
                     end_time,
                     color,
+                    is_selected: self.selected_objects.contains(&idx),
                     circle_is_hit,
                 });
             }
@@ -103,11 +107,22 @@ impl Game {
         for draw_info in playfield_hitobjects.iter() {
             let ho = draw_info.hit_object;
             let ho_time = ho.inner.start_time.as_seconds();
+            if ho.inner.kind.is_spinner() {
+                self.draw_spinner(ctx, draw_info, current_time)?;
+                continue;
+            }
+
             let stacking = ho.stacking as f32 * STACK_DISTANCE as f32;
             let pos = [
                 PLAYFIELD_BOUNDS.x + osupx_scale_x * (ho.inner.pos.x as f32 - stacking),
                 PLAYFIELD_BOUNDS.y + osupx_scale_y * (ho.inner.pos.y as f32 - stacking),
             ];
+            // the tail of a slider is offset by the same amount as its head
+            let end_pos = ho.inner.end_pos();
+            let end_pos = [
+                PLAYFIELD_BOUNDS.x + osupx_scale_x * (end_pos.x as f32 - stacking),
+                PLAYFIELD_BOUNDS.y + osupx_scale_y * (end_pos.y as f32 - stacking),
+            ];
             let mut color = draw_info.color;
             color.a = 0.6 * draw_info.fade_opacity as f32;
 
@@ -116,6 +131,7 @@ impl Game {
                 let mut control_points = vec![ho.inner.pos];
                 control_points.extend(&info.control_points);
 
+                let (track_color, border_color) = self.slider_colors(color);
                 Game::render_slider_body(
                     &mut self.slider_cache,
                     info,
@@ -123,15 +139,11 @@ impl Game {
                     ctx,
                     PLAYFIELD_BOUNDS,
                     &self.beatmap.inner,
-                    color,
+                    track_color,
+                    border_color,
                 )?;
                 slider_info = Some((info, control_points));
 
-                let end_pos = ho.inner.end_pos();
-                let end_pos = [
-                    PLAYFIELD_BOUNDS.x + osupx_scale_x * end_pos.x as f32,
-                    PLAYFIELD_BOUNDS.y + osupx_scale_y * end_pos.y as f32,
-                ];
                 self.skin.hitcircle.draw(
                     ctx,
                     (cs_real * 2.0, cs_real * 2.0),
@@ -142,6 +154,13 @@ impl Game {
                     (cs_real * 2.0, cs_real * 2.0),
                     DrawParam::default().dest(end_pos),
                 )?;
+                if draw_info.is_selected {
+                    self.skin.hitcircleselect.draw(
+                        ctx,
+                        (cs_real * 2.0, cs_real * 2.0),
+                        DrawParam::default().dest(end_pos),
+                    )?;
+                }
             }
 
             // draw main hitcircle
@@ -160,10 +179,90 @@ impl Game {
             // draw numbers
             self.draw_numbers_on_circle(ctx, ho.number, pos, cs_real, faded_color)?;
 
+            // show the additions on the head, and on the end of sliders
+            let additions = edge_additions(&ho.inner);
+            let marker_offset = cs_real * 1.15;
+            let marker_radius = cs_real * 0.12;
+            draw_addition_markers(
+                ctx,
+                additions[0],
+                [pos[0], pos[1] + marker_offset],
+                marker_radius,
+            )?;
+            if additions.len() > 1 {
+                // the last edge that's at the end rather than back at the head
+                let last = additions.len() - 1;
+                let end_edge = if last % 2 == 1 { last } else { last - 1 };
+                draw_addition_markers(
+                    ctx,
+                    additions[end_edge],
+                    [end_pos[0], end_pos[1] + marker_offset],
+                    marker_radius,
+                )?;
+            }
+
+            if draw_info.is_selected {
+                self.skin.hitcircleselect.draw(
+                    ctx,
+                    (cs_real * 2.0, cs_real * 2.0),
+                    DrawParam::default().dest(pos),
+                )?;
+            }
+
             if let Some((info, control_points)) = slider_info {
-                let spline = self.slider_cache.get(&control_points).unwrap();
+                let key = SliderCacheKey::new(info, &control_points);
+                let spline = self.slider_cache.get(&key).unwrap();
                 Game::render_slider_wireframe(ctx, &control_points, PLAYFIELD_BOUNDS, faded_color)?;
 
+                // reverse arrows show on whichever ends the slider still has to turn around at.
+                // it turns at the tail after odd spans and back at the head after even ones.
+                let num_repeats = info.num_repeats as usize;
+                let span_duration = (draw_info.end_time - ho_time) / num_repeats.max(1) as f64;
+                let to_screen = |point: Point<f64>| {
+                    [
+                        PLAYFIELD_BOUNDS.x + osupx_scale_x * (point.x as f32 - stacking),
+                        PLAYFIELD_BOUNDS.y + osupx_scale_y * (point.y as f32 - stacking),
+                    ]
+                };
+                let last_turn_at = |parity: usize| {
+                    (1..num_repeats)
+                        .filter(|turn| turn % 2 == parity)
+                        .last()
+                        .map(|turn| ho_time + turn as f64 * span_duration)
+                };
+                let arrow_color = Color::new(1.0, 1.0, 1.0, draw_info.fade_opacity as f32);
+                let ends = [
+                    // the arrow at the head only shows up once the head has been hit
+                    (last_turn_at(0), 0.0, 1.0f64.min(info.pixel_length), ho_time),
+                    (
+                        last_turn_at(1),
+                        info.pixel_length,
+                        (info.pixel_length - 1.0).max(0.0),
+                        f64::MIN,
+                    ),
+                ];
+                for (last_turn, at, towards, shown_after) in ends.iter() {
+                    match last_turn {
+                        Some(last_turn) if current_time < *last_turn => {}
+                        _ => continue,
+                    }
+                    if current_time < *shown_after {
+                        continue;
+                    }
+
+                    let arrow_pos = to_screen(spline.point_at_length(*at));
+                    let towards = to_screen(spline.point_at_length(*towards));
+                    let rotation = (towards[1] - arrow_pos[1]).atan2(towards[0] - arrow_pos[0]);
+                    self.skin.reversearrow.draw(
+                        ctx,
+                        (cs_real * 2.0, cs_real * 2.0),
+                        DrawParam::default()
+                            .dest(arrow_pos)
+                            .rotation(rotation)
+                            .color(arrow_color),
+                    )?;
+                }
+
                 if current_time >= ho_time && current_time <= draw_info.end_time {
                     let elapsed_time = current_time - ho_time;
                     let total_duration = draw_info.end_time - ho_time;
@@ -207,4 +306,49 @@ impl Game {
 
         Ok(())
     }
+
+    fn draw_spinner(
+        &self,
+        ctx: &mut Context,
+        draw_info: &DrawInfo,
+        current_time: f64,
+    ) -> Result<()> {
+        let ho_time = draw_info.hit_object.inner.start_time.as_seconds();
+        let pos = spinner_screen_pos();
+        let size = PLAYFIELD_BOUNDS.h * SPINNER_SIZE;
+        let alpha = draw_info.fade_opacity.min(1.0) as f32;
+
+        // spin at a constant rate once the spinner starts
+        let elapsed = (current_time - ho_time).max(0.0);
+        let rotation = (elapsed * std::f64::consts::PI * 2.0) as f32;
+        self.skin.spinnercircle.draw(
+            ctx,
+            (size, size),
+            DrawParam::default()
+                .dest(pos)
+                .rotation(rotation)
+                .color(Color::new(1.0, 1.0, 1.0, alpha)),
+        )?;
+
+        if draw_info.is_selected {
+            self.skin
+                .hitcircleselect
+                .draw(ctx, (size, size), DrawParam::default().dest(pos))?;
+        }
+
+        // the approach circle shrinks over the duration of the spinner
+        if current_time >= ho_time && current_time <= draw_info.end_time {
+            let progress = (current_time - ho_time) / (draw_info.end_time - ho_time);
+            let approach_size = size * (1.0 - progress as f32);
+            self.skin.spinnerapproachcircle.draw(
+                ctx,
+                (approach_size, approach_size),
+                DrawParam::default()
+                    .dest(pos)
+                    .color(Color::new(1.0, 1.0, 1.0, alpha)),
+            )?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,347 @@
+use anyhow::Result;
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Mesh},
+    Context,
+};
+use imgui::{Condition, Slider, Ui, Window};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind, SliderInfo},
+    hitsounds::{Additions, SampleSet},
+};
+
+use super::{ui::UiAction, Game};
+
+/// The additions that can be toggled, with their names and the key that toggles them.
+pub const ADDITIONS: &[(Additions, &str, &str)] = &[
+    (Additions::WHISTLE, "Whistle", "W"),
+    (Additions::FINISH, "Finish", "E"),
+    (Additions::CLAP, "Clap", "R"),
+];
+
+/// Colors of the dots that show which additions an object has.
+const ADDITION_COLORS: &[(Additions, Color)] = &[
+    (Additions::WHISTLE, Color::new(0.3, 0.8, 1.0, 1.0)),
+    (Additions::FINISH, Color::new(1.0, 0.85, 0.2, 1.0)),
+    (Additions::CLAP, Color::new(1.0, 0.4, 0.4, 1.0)),
+];
+
+/// "Auto" takes the sample set from the timing point (or, for the addition set, from the normal
+/// sample set).
+const SAMPLE_SETS: &[&str] = &["Auto", "Normal", "Soft", "Drum"];
+
+fn sample_set_index(sample_set: SampleSet) -> usize {
+    match sample_set {
+        SampleSet::Normal => 1,
+        SampleSet::Soft => 2,
+        SampleSet::Drum => 3,
+        _ => 0,
+    }
+}
+
+fn sample_set_from_index(idx: usize) -> SampleSet {
+    match idx {
+        1 => SampleSet::Normal,
+        2 => SampleSet::Soft,
+        3 => SampleSet::Drum,
+        _ => SampleSet::default(),
+    }
+}
+
+/// The additions on each edge of an object: just one for circles and spinners, and one for the
+/// head, every repeat and the tail of a slider.
+pub(super) fn edge_additions(ho: &HitObject) -> Vec<Additions> {
+    match &ho.kind {
+        HitObjectKind::Slider(info) => (0..=info.num_repeats as usize)
+            .map(|edge| {
+                info.edge_additions
+                    .get(edge)
+                    .cloned()
+                    .unwrap_or(ho.additions)
+            })
+            .collect(),
+        _ => vec![ho.additions],
+    }
+}
+
+/// Gives every edge of a slider its own additions and sample sets, starting from the ones on the
+/// whole object, so a single edge can be changed.
+fn fill_edges(info: &mut SliderInfo, additions: Additions, sets: (SampleSet, SampleSet)) {
+    let edges = info.num_repeats as usize + 1;
+    info.edge_additions.resize(edges, additions);
+    info.edge_samplesets.resize(edges, sets);
+}
+
+/// Draws a dot for each addition, in a row centered on `pos`.
+pub(super) fn draw_addition_markers(
+    ctx: &mut Context,
+    additions: Additions,
+    pos: [f32; 2],
+    radius: f32,
+) -> Result<()> {
+    let colors = ADDITION_COLORS
+        .iter()
+        .filter(|(addition, _)| additions.contains(*addition))
+        .map(|(_, color)| *color)
+        .collect::<Vec<_>>();
+    let spacing = radius * 2.5;
+    let left = pos[0] - spacing * (colors.len() as f32 - 1.0) / 2.0;
+    for (i, color) in colors.into_iter().enumerate() {
+        let dot = Mesh::new_circle(
+            ctx,
+            DrawMode::fill(),
+            [left + spacing * i as f32, pos[1]],
+            radius,
+            0.5,
+            color,
+        )?;
+        graphics::draw(ctx, &dot, DrawParam::default())?;
+    }
+    Ok(())
+}
+
+/// The hitsounds of whatever is being edited, to show in the hitsounds panel.
+#[derive(Debug)]
+pub struct HitsoundSummary {
+    /// Number of edges of the selected slider, if a single slider is selected.
+    slider_edges: Option<usize>,
+    /// The edge of the slider being edited, or None if whole objects are being edited.
+    edge: Option<usize>,
+    /// The additions that all of the edited objects have.
+    additions: Additions,
+    sample_set: SampleSet,
+    addition_set: SampleSet,
+    custom_index: i32,
+    volume: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct HitsoundPanelState {
+    pub opened: bool,
+    custom_index: i32,
+    volume: i32,
+    /// The custom index and volume of the selection when they were last loaded, so they're only
+    /// reloaded when the selection actually changes and not in the middle of being edited.
+    loaded: Option<(i32, i32)>,
+}
+
+pub(super) fn draw_hitsound_panel(
+    ui: &Ui,
+    state: &mut HitsoundPanelState,
+    summary: Option<&HitsoundSummary>,
+    actions: &mut Vec<UiAction>,
+) {
+    if !state.opened {
+        return;
+    }
+
+    let mut opened = true;
+    Window::new("Hitsounds")
+        .opened(&mut opened)
+        .size([280.0, 240.0], Condition::FirstUseEver)
+        .build(ui, || {
+            let summary = match summary {
+                Some(summary) => summary,
+                None => {
+                    ui.text_disabled("Select objects to change their hitsounds.");
+                    state.loaded = None;
+                    return;
+                }
+            };
+
+            if state.loaded != Some((summary.custom_index, summary.volume)) {
+                state.custom_index = summary.custom_index;
+                state.volume = summary.volume;
+                state.loaded = Some((summary.custom_index, summary.volume));
+            }
+
+            if let Some(edges) = summary.slider_edges {
+                let mut names = vec!["Whole Slider".to_owned(), "Head".to_owned()];
+                names.extend((1..edges - 1).map(|repeat| format!("Repeat {}", repeat)));
+                names.push("Tail".to_owned());
+                let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+
+                let mut target = summary.edge.map_or(0, |edge| edge + 1);
+                if ui.combo_simple_string("Edit", &mut target, &names) {
+                    let edge = if target == 0 { None } else { Some(target - 1) };
+                    actions.push(UiAction::SetHitsoundEdge(edge));
+                }
+            }
+
+            for (addition, name, key) in ADDITIONS.iter() {
+                let mut enabled = summary.additions.contains(*addition);
+                if ui.checkbox(&format!("{} <{}>", name, key), &mut enabled) {
+                    actions.push(UiAction::ToggleAddition(*addition));
+                }
+            }
+
+            let mut sample_set = sample_set_index(summary.sample_set);
+            if ui.combo_simple_string("Sample Set", &mut sample_set, SAMPLE_SETS) {
+                actions.push(UiAction::SetSampleSet(sample_set_from_index(sample_set)));
+            }
+            let mut addition_set = sample_set_index(summary.addition_set);
+            if ui.combo_simple_string("Addition Set", &mut addition_set, SAMPLE_SETS) {
+                actions.push(UiAction::SetAdditionSet(sample_set_from_index(
+                    addition_set,
+                )));
+            }
+
+            // these are stored on the whole object, even for slider edges
+            ui.separator();
+            ui.input_int("Custom Index", &mut state.custom_index)
+                .build();
+            if ui.is_item_deactivated_after_edit() {
+                actions.push(UiAction::SetCustomIndex(state.custom_index.max(0)));
+            }
+            Slider::new("Volume", 0, 100).build(ui, &mut state.volume);
+            if ui.is_item_deactivated_after_edit() {
+                actions.push(UiAction::SetSampleVolume(state.volume));
+            }
+            ui.text_disabled("0 uses the timing point's value.");
+        });
+    state.opened = opened;
+}
+
+impl Game {
+    /// The slider edge that hitsound changes apply to, if a single slider is selected and one of
+    /// its edges was picked.
+    fn hitsound_edge(&self) -> Option<usize> {
+        let (slider, edge) = self.hitsound_edge?;
+        match self.selected_objects.as_slice() {
+            [idx] if *idx == slider => match &self.beatmap.hit_objects[*idx].inner.kind {
+                HitObjectKind::Slider(info) if edge <= info.num_repeats as usize => Some(edge),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub(super) fn set_hitsound_edge(&mut self, edge: Option<usize>) {
+        self.hitsound_edge = match (self.selected_objects.as_slice(), edge) {
+            ([idx], Some(edge)) => Some((*idx, edge)),
+            _ => None,
+        };
+    }
+
+    pub(super) fn hitsound_summary(&self) -> Option<HitsoundSummary> {
+        let edge = self.hitsound_edge();
+        let first = &self.beatmap.hit_objects[*self.selected_objects.first()?].inner;
+
+        let slider_edges = match (self.selected_objects.len(), &first.kind) {
+            (1, HitObjectKind::Slider(info)) => Some(info.num_repeats as usize + 1),
+            _ => None,
+        };
+        let (sample_set, addition_set) = match (&first.kind, edge) {
+            (HitObjectKind::Slider(info), Some(edge)) => info
+                .edge_samplesets
+                .get(edge)
+                .cloned()
+                .unwrap_or((first.sample_info.sample_set, first.sample_info.addition_set)),
+            _ => (first.sample_info.sample_set, first.sample_info.addition_set),
+        };
+
+        let mut additions = Additions::all();
+        for idx in self.selected_objects.iter() {
+            let ho = &self.beatmap.hit_objects[*idx].inner;
+            match edge {
+                Some(edge) => additions &= edge_additions(ho)[edge],
+                None => {
+                    additions &= ho.additions;
+                    if let HitObjectKind::Slider(info) = &ho.kind {
+                        for edge in info.edge_additions.iter() {
+                            additions &= *edge;
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(HitsoundSummary {
+            slider_edges,
+            edge,
+            additions,
+            sample_set,
+            addition_set,
+            custom_index: first.sample_info.custom_index as i32,
+            volume: first.sample_info.sample_volume as i32,
+        })
+    }
+
+    /// Changes the additions and sample sets of whatever is being edited: either one edge of the
+    /// selected slider, or every selected object as a whole (including all the edges of sliders).
+    fn edit_hitsounds(
+        &mut self,
+        name: &str,
+        f: impl Fn(&mut Additions, &mut SampleSet, &mut SampleSet),
+    ) {
+        let edge = self.hitsound_edge();
+        self.edit_selection(name, |ho| {
+            let additions = ho.additions;
+            let sets = (ho.sample_info.sample_set, ho.sample_info.addition_set);
+            if let HitObjectKind::Slider(info) = &mut ho.kind {
+                if let Some(edge) = edge {
+                    fill_edges(info, additions, sets);
+                    let (sample_set, addition_set) = &mut info.edge_samplesets[edge];
+                    f(&mut info.edge_additions[edge], sample_set, addition_set);
+                    return;
+                }
+
+                let edges = info.edge_additions.len().max(info.edge_samplesets.len());
+                for edge in 0..edges {
+                    let mut new_additions =
+                        info.edge_additions.get(edge).cloned().unwrap_or(additions);
+                    let mut new_sets = info.edge_samplesets.get(edge).cloned().unwrap_or(sets);
+                    f(&mut new_additions, &mut new_sets.0, &mut new_sets.1);
+
+                    // only edges that have their own values are changed, the rest follow the
+                    // object
+                    if let Some(additions) = info.edge_additions.get_mut(edge) {
+                        *additions = new_additions;
+                    }
+                    if let Some(sets) = info.edge_samplesets.get_mut(edge) {
+                        *sets = new_sets;
+                    }
+                }
+            }
+
+            f(
+                &mut ho.additions,
+                &mut ho.sample_info.sample_set,
+                &mut ho.sample_info.addition_set,
+            );
+        });
+    }
+
+    /// Toggles an addition on whatever is being edited. If only some of it has the addition, all
+    /// of it gets it.
+    pub(super) fn toggle_addition(&mut self, addition: Additions) {
+        let enabled = match self.hitsound_summary() {
+            Some(summary) => !summary.additions.contains(addition),
+            None => return,
+        };
+        let name = ADDITIONS
+            .iter()
+            .find(|(a, _, _)| *a == addition)
+            .map_or("Change additions", |(_, name, _)| *name);
+        self.edit_hitsounds(name, |additions, _, _| additions.set(addition, enabled));
+    }
+
+    pub(super) fn set_sample_set(&mut self, sample_set: SampleSet) {
+        self.edit_hitsounds("Change sample set", |_, set, _| *set = sample_set);
+    }
+
+    pub(super) fn set_addition_set(&mut self, addition_set: SampleSet) {
+        self.edit_hitsounds("Change addition set", |_, _, set| *set = addition_set);
+    }
+
+    pub(super) fn set_custom_index(&mut self, custom_index: i32) {
+        self.edit_selection("Change custom index", |ho| {
+            ho.sample_info.custom_index = custom_index.max(0) as _;
+        });
+    }
+
+    pub(super) fn set_sample_volume(&mut self, volume: i32) {
+        self.edit_selection("Change volume", |ho| {
+            ho.sample_info.sample_volume = volume.max(0).min(100) as _;
+        });
+    }
+}
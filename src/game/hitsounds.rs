@@ -0,0 +1,442 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    hitsounds::{Additions, SampleSet},
+    timing::{Millis, TimingPointKind},
+};
+
+use crate::audio::Sound;
+use crate::utils;
+
+use super::Game;
+
+/// Where the default hitsounds are looked up when the beatmap doesn't have its own.
+pub const SKIN_DIR: &str = "skin";
+
+/// Hitsounds are played a bit quieter than their timing point volume so they don't drown out the
+/// music.
+pub const HITSOUND_VOLUME: f32 = 0.3;
+
+/// How far hitsounds are panned towards the side of the playfield the object is on, from 0 (not
+/// at all) to 1 (all the way).
+pub const HITSOUND_PAN: f32 = 0.8;
+
+/// If playback jumps further than this (in seconds) between frames, the objects in between are
+/// skipped instead of all being played at once.
+const MAX_FRAME_GAP: f64 = 0.5;
+
+/// File extensions that samples are looked up with, in the order osu! tries them.
+const SAMPLE_EXTENSIONS: &[&str] = &["wav", "ogg", "mp3"];
+
+#[derive(Default)]
+pub struct HitsoundState {
+    /// Time of the song the last time hitsounds were played, or None if playback just started.
+    last_time: Option<f64>,
+    /// Slider slide loops by path. None means the file couldn't be loaded, so we don't keep
+    /// retrying.
+    loops: HashMap<PathBuf, Option<Sound>>,
+    /// Samples that failed to load, so the error is only logged once.
+    failed_samples: HashSet<PathBuf>,
+    /// Slider slide loops that are currently playing.
+    playing_loops: HashSet<PathBuf>,
+}
+
+impl HitsoundState {
+    fn load_loop(&mut self, path: &Path) -> Option<&Sound> {
+        self.loops
+            .entry(path.to_path_buf())
+            .or_insert_with(|| match Sound::create_looping(path) {
+                Ok(sound) => Some(sound),
+                Err(err) => {
+                    warn!("failed to load hitsound {:?}: {}", path, err);
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+/// Which samples to play and how loud, before they get resolved to actual files.
+struct HitsoundEvent {
+    time: f64,
+    /// Horizontal position on the playfield, used for panning.
+    x: i32,
+    sample_set: SampleSet,
+    addition_set: SampleSet,
+    additions: Additions,
+    custom_index: u32,
+    volume: u32,
+    kind: HitsoundEventKind,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum HitsoundEventKind {
+    /// Circles, slider heads, repeats and tails.
+    Hit,
+    SliderTick,
+}
+
+fn sample_set_name(sample_set: SampleSet) -> Option<&'static str> {
+    match sample_set {
+        SampleSet::Normal => Some("normal"),
+        SampleSet::Soft => Some("soft"),
+        SampleSet::Drum => Some("drum"),
+        _ => None,
+    }
+}
+
+impl Game {
+    /// Plays the hitsounds for every object the playhead has crossed since the last frame, and
+    /// keeps slider slide loops going.
+    pub(super) fn update_hitsounds(&mut self, time: f64) -> Result<()> {
+        if !self.is_playing {
+            self.stop_hitsounds();
+            return Ok(());
+        }
+
+        let last_time = match self.hitsounds.last_time.replace(time) {
+            Some(last_time) if last_time <= time && time - last_time <= MAX_FRAME_GAP => last_time,
+            _ => return Ok(()),
+        };
+
+        let mut events = Vec::new();
+        let mut loops = HashSet::new();
+        for ho in self.beatmap.hit_objects.iter() {
+            let start_time = ho.inner.start_time.as_seconds();
+            if start_time > time {
+                break;
+            }
+
+            // sliders without a timing point to time them by have no end, and can't be played
+            let end_time = match self.beatmap.inner.get_hitobject_end_time(&ho.inner) {
+                Some(end_time) => end_time,
+                None => continue,
+            };
+            if end_time < last_time {
+                continue;
+            }
+
+            for event in self.hitsound_events(&ho.inner) {
+                if event.time > last_time && event.time <= time {
+                    events.push(event);
+                }
+            }
+
+            if start_time <= time && time < end_time {
+                loops.extend(self.slide_loops(&ho.inner));
+            }
+        }
+
+        for event in events {
+            self.play_hitsound_event(&event)?;
+        }
+        self.update_slide_loops(loops)?;
+
+        Ok(())
+    }
+
+    /// Stops everything that's playing and forgets where playback was.
+    pub(super) fn stop_hitsounds(&mut self) {
+        self.hitsounds.last_time = None;
+        for path in self.hitsounds.playing_loops.drain() {
+            if let Some(Some(sound)) = self.hitsounds.loops.get(&path) {
+                if let Err(err) = self.audio_engine.pause(sound) {
+                    warn!("failed to stop hitsound {:?}: {}", path, err);
+                }
+            }
+        }
+    }
+
+    /// Throws away all loaded hitsounds, for example when a different beatmap is loaded.
+    pub(super) fn reset_hitsounds(&mut self) {
+        self.stop_hitsounds();
+        self.hitsounds.loops.clear();
+        self.hitsounds.failed_samples.clear();
+        self.audio_engine.clear_samples();
+    }
+
+    fn hitsound_events(&self, ho: &HitObject) -> Vec<HitsoundEvent> {
+        let sample_info = &ho.sample_info;
+        let event =
+            |time: f64, x: i32, additions: Additions, sets: (SampleSet, SampleSet), kind| {
+                HitsoundEvent {
+                    time,
+                    x,
+                    sample_set: sets.0,
+                    addition_set: sets.1,
+                    additions,
+                    custom_index: sample_info.custom_index as u32,
+                    volume: sample_info.sample_volume as u32,
+                    kind,
+                }
+            };
+        let default_sets = (sample_info.sample_set, sample_info.addition_set);
+
+        let start_time = ho.start_time.as_seconds();
+        let info = match &ho.kind {
+            HitObjectKind::Slider(info) => info,
+            HitObjectKind::Circle => {
+                return vec![event(
+                    start_time,
+                    ho.pos.x,
+                    ho.additions,
+                    default_sets,
+                    HitsoundEventKind::Hit,
+                )];
+            }
+            // spinners only make a sound when they end
+            HitObjectKind::Spinner(spinner) => {
+                return vec![event(
+                    spinner.end_time.as_seconds(),
+                    ho.pos.x,
+                    ho.additions,
+                    default_sets,
+                    HitsoundEventKind::Hit,
+                )];
+            }
+        };
+
+        let mut events = Vec::new();
+        let num_repeats = info.num_repeats.max(1) as usize;
+        let duration = match self.beatmap.inner.get_slider_duration(ho) {
+            Some(duration) => duration,
+            None => return Vec::new(),
+        };
+        let span_duration = duration / num_repeats as f64;
+        let end_x = ho.end_pos().x as i32;
+
+        // the head, every repeat and the tail each have their own hitsounds
+        for edge in 0..=num_repeats {
+            let additions = info
+                .edge_additions
+                .get(edge)
+                .cloned()
+                .unwrap_or(ho.additions);
+            let sets = info
+                .edge_samplesets
+                .get(edge)
+                .cloned()
+                .unwrap_or(default_sets);
+            let time = start_time + edge as f64 * span_duration;
+            // even edges are at the head of the slider, odd ones at the end
+            let x = if edge % 2 == 0 { ho.pos.x } else { end_x };
+            events.push(event(time, x, additions, sets, HitsoundEventKind::Hit));
+        }
+
+        // ticks are placed every 1 / tick rate beats along each span, and mirrored on the way back
+        if let Some(tick_duration) = self.slider_tick_duration(ho.start_time) {
+            // ticks too close to the end of a span are skipped, like in osu!
+            let min_distance_from_end = 0.01;
+            for span in 0..num_repeats {
+                let span_start = start_time + span as f64 * span_duration;
+                let mut offset = tick_duration;
+                while offset < span_duration - min_distance_from_end {
+                    let time = if span % 2 == 0 {
+                        span_start + offset
+                    } else {
+                        span_start + span_duration - offset
+                    };
+                    events.push(event(
+                        time,
+                        ho.pos.x,
+                        Additions::empty(),
+                        default_sets,
+                        HitsoundEventKind::SliderTick,
+                    ));
+                    offset += tick_duration;
+                }
+            }
+        }
+
+        events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        events
+    }
+
+    /// Time between slider ticks (in seconds) for a slider starting at the given time.
+    fn slider_tick_duration(&self, start_time: Millis) -> Option<f64> {
+        let tick_rate = self.beatmap.inner.difficulty.slider_tick_rate as f64;
+        if tick_rate <= 0.0 {
+            return None;
+        }
+
+        match self
+            .beatmap
+            .uninherited_timing_point_at(start_time.as_seconds())
+        {
+            Some(timing_point) => match &timing_point.kind {
+                TimingPointKind::Uninherited(info) if info.mpb > 0.0 => {
+                    Some(info.mpb / 1000.0 / tick_rate)
+                }
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    /// The looping samples that play while a slider is being held.
+    fn slide_loops(&self, ho: &HitObject) -> Vec<PathBuf> {
+        if !ho.kind.is_slider() {
+            return Vec::new();
+        }
+
+        let (sample_set, addition_set, index, _) = self.resolve_sample_info(
+            ho.start_time.as_seconds(),
+            ho.sample_info.sample_set,
+            ho.sample_info.addition_set,
+            ho.sample_info.custom_index as u32,
+            ho.sample_info.sample_volume as u32,
+        );
+
+        let mut names = vec![(sample_set, "sliderslide")];
+        if ho.additions.contains(Additions::WHISTLE) {
+            names.push((addition_set, "sliderwhistle"));
+        }
+
+        names
+            .into_iter()
+            .filter_map(|(set, name)| self.find_sample(set, name, index))
+            .collect()
+    }
+
+    /// Fills in everything the object leaves as default from the timing point in effect and the
+    /// beatmap. Returns the sample set, addition set, custom index and volume (0-100).
+    fn resolve_sample_info(
+        &self,
+        time: f64,
+        sample_set: SampleSet,
+        addition_set: SampleSet,
+        custom_index: u32,
+        volume: u32,
+    ) -> (&'static str, &'static str, u32, u32) {
+        let timing_point = self.beatmap.timing_point_at(time);
+
+        let sample_set = sample_set_name(sample_set)
+            .or_else(|| timing_point.and_then(|tp| sample_set_name(tp.sample_set)))
+            .or_else(|| sample_set_name(self.beatmap.inner.sample_set))
+            .unwrap_or("normal");
+        let addition_set = sample_set_name(addition_set).unwrap_or(sample_set);
+
+        let custom_index = match (custom_index, timing_point) {
+            (0, Some(tp)) => tp.sample_index as u32,
+            (index, _) => index,
+        };
+        let volume = match (volume, timing_point) {
+            (0, Some(tp)) => tp.volume as u32,
+            (0, None) => 100,
+            (volume, _) => volume,
+        };
+
+        (sample_set, addition_set, custom_index, volume)
+    }
+
+    fn play_hitsound_event(&mut self, event: &HitsoundEvent) -> Result<()> {
+        let (sample_set, addition_set, index, volume) = self.resolve_sample_info(
+            event.time,
+            event.sample_set,
+            event.addition_set,
+            event.custom_index,
+            event.volume,
+        );
+
+        let mut names = Vec::new();
+        match event.kind {
+            HitsoundEventKind::SliderTick => names.push((sample_set, "slidertick")),
+            HitsoundEventKind::Hit => {
+                // the normal sound always plays, additions play on top of it
+                names.push((sample_set, "hitnormal"));
+                if event.additions.contains(Additions::WHISTLE) {
+                    names.push((addition_set, "hitwhistle"));
+                }
+                if event.additions.contains(Additions::FINISH) {
+                    names.push((addition_set, "hitfinish"));
+                }
+                if event.additions.contains(Additions::CLAP) {
+                    names.push((addition_set, "hitclap"));
+                }
+            }
+        }
+
+        let volume = volume as f32 / 100.0 * HITSOUND_VOLUME;
+        let pan = (event.x as f32 / 512.0 * 2.0 - 1.0).max(-1.0).min(1.0) * HITSOUND_PAN;
+        for (set, name) in names {
+            let path = match self.find_sample(set, name, index) {
+                Some(path) => path,
+                None => continue,
+            };
+            if self.hitsounds.failed_samples.contains(&path) {
+                continue;
+            }
+
+            if let Err(err) = self.audio_engine.play_sample(&path, volume, pan) {
+                warn!("failed to play hitsound {:?}: {}", path, err);
+                self.hitsounds.failed_samples.insert(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts the loops that should be playing and stops the rest.
+    fn update_slide_loops(&mut self, loops: HashSet<PathBuf>) -> Result<()> {
+        let stopped = self
+            .hitsounds
+            .playing_loops
+            .difference(&loops)
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in stopped {
+            if let Some(Some(sound)) = self.hitsounds.loops.get(&path) {
+                self.audio_engine.pause(sound)?;
+            }
+            self.hitsounds.playing_loops.remove(&path);
+        }
+
+        for path in loops {
+            if self.hitsounds.playing_loops.contains(&path) {
+                continue;
+            }
+
+            if let Some(sound) = self.hitsounds.load_loop(&path) {
+                sound.set_volume(HITSOUND_VOLUME)?;
+                self.audio_engine.play_from_start(sound)?;
+            }
+            self.hitsounds.playing_loops.insert(path);
+        }
+
+        Ok(())
+    }
+
+    /// Looks for a sample in the beatmap's folder first, and then in the skin.
+    ///
+    /// Custom index 0 always uses the skin's samples, index 1 uses the beatmap's sample without a
+    /// number, and anything higher uses the beatmap's sample with that number at the end.
+    fn find_sample(&self, sample_set: &str, name: &str, index: u32) -> Option<PathBuf> {
+        let beatmap_dir = self.beatmap_path.as_ref().and_then(|path| path.parent());
+        if let (Some(dir), true) = (beatmap_dir, index > 0) {
+            let suffix = if index == 1 {
+                String::new()
+            } else {
+                index.to_string()
+            };
+            let stem = format!("{}-{}{}", sample_set, name, suffix);
+            if let Some(path) = find_with_extension(dir, &stem) {
+                return Some(path);
+            }
+        }
+
+        let stem = format!("{}-{}", sample_set, name);
+        find_with_extension(Path::new(SKIN_DIR), &stem)
+    }
+}
+
+/// Finds the sample called `stem` in a folder with the first of `SAMPLE_EXTENSIONS` that exists.
+fn find_with_extension(dir: &Path, stem: &str) -> Option<PathBuf> {
+    SAMPLE_EXTENSIONS.iter().find_map(|extension| {
+        let filename = format!("{}.{}", stem, extension);
+        utils::fuck_you_windows(dir, &filename).ok().flatten()
+    })
+}
@@ -1,17 +1,32 @@
 mod background;
+mod bpm_detect;
+mod clipboard;
+mod combo;
+mod distance_snap;
 mod events;
+mod go_to;
 mod grid;
 mod hitobjects;
+mod hitsound_edit;
+mod hitsounds;
 mod numbers;
+mod repeats;
 mod seeker;
+mod select;
+mod slider_edit;
 mod sliders;
+mod song_setup;
+mod spectrogram;
 mod timeline;
+mod timeline_edit;
+mod timing;
+mod transform;
 mod ui;
 
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::Result;
@@ -26,19 +41,27 @@ use ggez::{
 use image::io::Reader as ImageReader;
 use libosu::{
     beatmap::Beatmap,
-    hitobject::{HitObjectKind, SliderSplineKind},
+    hitobject::{HitObjectKind, SliderInfo, SliderSplineKind},
     math::Point,
     spline::Spline,
     timing::{Millis, TimingPoint, TimingPointKind},
 };
 
 use crate::audio::{AudioEngine, Sound};
-use crate::beatmap::BeatmapExt;
-use crate::hitobject::HitObjectExt;
+use crate::beatmap::{rgb_to_f32, BeatmapExt};
+use crate::history::{Change, Command, History};
 use crate::imgui_wrapper::ImGuiWrapper;
 use crate::skin::Skin;
 use crate::utils::{self, rect_contains};
 
+use self::bpm_detect::TempoDetection;
+use self::hitsounds::HitsoundState;
+use self::select::osu_to_screen;
+use self::slider_edit::{AnchorDrag, SLIDER_KINDS};
+use self::song_setup::ColorPreview;
+use self::spectrogram::SpectrogramAnalysis;
+use self::timeline_edit::TimelineDrag;
+use self::transform::Transform;
 use self::ui::UiState;
 
 pub const PLAYFIELD_BOUNDS: Rect = Rect::new(112.0, 122.0, 800.0, 600.0);
@@ -49,7 +72,40 @@ pub const DEFAULT_COLORS: &[(f32, f32, f32)] = &[
     (0.95, 0.1, 0.22),
 ];
 
-pub type SliderCache = HashMap<Vec<Point<i32>>, Spline>;
+/// Beat snap divisors that can be picked in the editor.
+pub const BEAT_DIVISORS: &[u32] = &[1, 2, 3, 4, 6, 8, 12, 16];
+
+/// Speeds the song can be played back at.
+pub const PLAYBACK_RATES: &[f64] = &[0.25, 0.5, 0.75, 1.0];
+
+pub type SliderCache = HashMap<SliderCacheKey, Spline>;
+
+/// Everything that goes into the shape of a slider, so the cached spline is thrown out when any
+/// of it changes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SliderCacheKey {
+    kind: usize,
+    control_points: Vec<Point<i32>>,
+    /// The bits of the f64, since floats can't be hashed.
+    pixel_length: u64,
+}
+
+impl SliderCacheKey {
+    pub fn new(info: &SliderInfo, control_points: &[Point<i32>]) -> Self {
+        SliderCacheKey {
+            kind: SLIDER_KINDS
+                .iter()
+                .position(|(kind, _)| *kind == info.kind)
+                .unwrap_or(0),
+            control_points: control_points.to_vec(),
+            pixel_length: info.pixel_length.to_bits(),
+        }
+    }
+}
+
+pub struct PartialSpinnerState {
+    start_time: Millis,
+}
 
 pub struct PartialSliderState {
     start_time: Millis,
@@ -63,14 +119,21 @@ pub enum Tool {
     Select,
     Circle,
     Slider,
+    Spinner,
 }
 
 pub struct Game {
     is_playing: bool,
+    playback_rate: f64,
     imgui: ImGuiWrapper,
-    audio_engine: AudioEngine,
     song: Option<Sound>,
     beatmap: BeatmapExt,
+    beatmap_path: Option<PathBuf>,
+    history: History,
+    hitsounds: HitsoundState,
+    /// The slider and the edge of it that hitsound changes apply to. The edge is only used while
+    /// that slider is the one that's selected.
+    hitsound_edge: Option<(usize, usize)>,
     pub skin: Skin,
     background_image: Option<Image>,
     ui_state: Option<UiState>,
@@ -81,8 +144,22 @@ pub struct Game {
     seeker_cache: Option<CanvasGeneric<GlBackendSpec>>,
     combo_colors: Vec<Color>,
     selected_objects: Vec<usize>,
+    selection_drag_anchor: Option<usize>,
+    anchor_drag: Option<AnchorDrag>,
+    /// The slider whose end is being dragged along the timeline to change its repeats.
+    repeat_drag: Option<usize>,
+    timeline_drag: Option<TimelineDrag>,
+    /// Whether new objects are snapped to a distance from the previous object.
+    distance_snap: bool,
+    /// The transform being set up in the transform dialog, shown on the playfield.
+    transform_preview: Option<Transform>,
+    /// The colors being edited in Song Setup, shown instead of the beatmap's.
+    color_preview: Option<ColorPreview>,
+    spectrogram: Option<SpectrogramAnalysis>,
+    tempo_detection: Option<TempoDetection>,
     tool: Tool,
     partial_slider_state: Option<PartialSliderState>,
+    partial_spinner_state: Option<PartialSpinnerState>,
 
     keymap: HashSet<KeyCode>,
     mouse_pos: (f32, f32),
@@ -90,6 +167,9 @@ pub struct Game {
     right_drag_start: Option<(f32, f32)>,
     current_uninherited_timing_point: Option<TimingPoint>,
     current_inherited_timing_point: Option<TimingPoint>,
+
+    // declared last so the song and every other stream is freed before the device is
+    audio_engine: AudioEngine,
 }
 
 impl Game {
@@ -102,9 +182,14 @@ impl Game {
 
         Ok(Game {
             is_playing: false,
+            playback_rate: 1.0,
             imgui,
             audio_engine,
             beatmap,
+            beatmap_path: None,
+            history: History::default(),
+            hitsounds: HitsoundState::default(),
+            hitsound_edge: None,
             song: None,
             skin,
             ui_state: Some(UiState::default()),
@@ -118,12 +203,22 @@ impl Game {
                 .collect(),
             background_image: None,
             selected_objects: vec![],
+            selection_drag_anchor: None,
+            anchor_drag: None,
+            repeat_drag: None,
+            timeline_drag: None,
+            distance_snap: true,
+            transform_preview: None,
+            color_preview: None,
+            spectrogram: None,
+            tempo_detection: None,
             keymap: HashSet::new(),
             mouse_pos: (-1.0, -1.0),
             left_drag_start: None,
             right_drag_start: None,
             tool: Tool::Select,
             partial_slider_state: None,
+            partial_spinner_state: None,
             current_uninherited_timing_point: None,
             current_inherited_timing_point: None,
         })
@@ -138,30 +233,13 @@ impl Game {
 
         let beatmap = Beatmap::from_str(&contents)?;
         self.beatmap = BeatmapExt::new(beatmap);
+        self.beatmap.read_slider_colors(&contents);
+        self.beatmap_path = Some(path.to_path_buf());
+        self.history.clear();
+        self.reset_hitsounds();
         self.beatmap.compute_stacking();
 
-        if !self.beatmap.inner.colors.is_empty() {
-            self.combo_colors.clear();
-            self.combo_colors = self
-                .beatmap
-                .inner
-                .colors
-                .iter()
-                .map(|color| {
-                    Color::new(
-                        color.red as f32 / 255.0,
-                        color.green as f32 / 255.0,
-                        color.blue as f32 / 255.0,
-                        1.0,
-                    )
-                })
-                .collect();
-        } else {
-            self.combo_colors = DEFAULT_COLORS
-                .iter()
-                .map(|(r, g, b)| Color::new(*r, *g, *b, 1.0))
-                .collect();
-        }
+        self.update_combo_colors();
         self.beatmap.compute_colors(&self.combo_colors);
 
         let dir = path.parent().unwrap();
@@ -185,29 +263,81 @@ impl Game {
             }
         }
 
-        let song = Sound::create(dir.join(&self.beatmap.inner.audio_filename))?;
-        song.set_volume(0.1);
+        let mut song = Sound::create(dir.join(&self.beatmap.inner.audio_filename))?;
+        song.set_volume(0.1)?;
+        song.set_playback_rate(self.playback_rate)?;
+        song.decode_waveform();
         self.song = Some(song);
+        self.spectrogram = None;
+        self.tempo_detection = None;
         self.timestamp_changed()?;
 
         Ok(())
     }
 
+    pub fn save_beatmap(&mut self) -> Result<()> {
+        let path = match &self.beatmap_path {
+            Some(path) => path.clone(),
+            None => bail!("no beatmap loaded"),
+        };
+
+        self.beatmap.save(&path)?;
+        info!("saved beatmap to {:?}", path);
+        Ok(())
+    }
+
+    /// Throws away all unsaved changes by reloading the beatmap from disk.
+    pub fn revert_beatmap(&mut self, ctx: &mut Context) -> Result<()> {
+        let path = match &self.beatmap_path {
+            Some(path) => path.clone(),
+            None => bail!("no beatmap loaded"),
+        };
+
+        if self.is_playing {
+            self.toggle_playing()?;
+        }
+        let time = match &self.song {
+            Some(song) => Some(song.position()?),
+            None => None,
+        };
+
+        self.selected_objects.clear();
+        self.hitsound_edge = None;
+        self.partial_slider_state = None;
+        self.slider_cache.clear();
+        self.load_beatmap(ctx, &path)?;
+
+        if let Some(time) = time {
+            self.jump_to_time(time)?;
+            self.timestamp_changed()?;
+        }
+        Ok(())
+    }
+
     pub fn jump_to_time(&mut self, time: f64) -> Result<()> {
+        // don't play everything between the old and new position
+        self.stop_hitsounds();
         if let Some(song) = &self.song {
             song.set_position(time)?;
         }
         Ok(())
     }
 
-    pub fn toggle_playing(&mut self) {
+    pub fn toggle_playing(&mut self) -> Result<()> {
+        let song = match &self.song {
+            Some(song) => song,
+            None => return Ok(()),
+        };
+
         if self.is_playing {
+            self.audio_engine.pause(song)?;
             self.is_playing = false;
-            self.audio_engine.pause(self.song.as_ref().unwrap());
+            self.stop_hitsounds();
         } else {
+            self.audio_engine.play(song)?;
             self.is_playing = true;
-            self.audio_engine.play(self.song.as_ref().unwrap());
         }
+        Ok(())
     }
 
     fn draw_helper(&mut self, ctx: &mut Context) -> Result<()> {
@@ -222,14 +352,20 @@ impl Game {
         let time_millis = Millis::from_seconds(time);
         let text = Text::new(
             format!(
-                "tool: {:?} time: {:.4}, mouse: {:?}",
-                self.tool, time, self.mouse_pos
+                "tool: {:?} time: {:.4}, divisor: 1/{}, rate: {}%, mouse: {:?}",
+                self.tool,
+                time,
+                self.beat_divisor(),
+                self.playback_rate * 100.0,
+                self.mouse_pos
             )
             .as_ref(),
         );
         graphics::queue_text(ctx, &text, [0.0, 0.0], Some(Color::WHITE));
         graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)?;
 
+        self.draw_waveform(ctx, time)?;
+
         self.draw_hitobjects(ctx, time)?;
 
         self.draw_timeline(ctx, time)?;
@@ -246,11 +382,23 @@ impl Game {
         let pos_x = (mx - PLAYFIELD_BOUNDS.x) / PLAYFIELD_BOUNDS.w * 512.0;
         let pos_y = (my - PLAYFIELD_BOUNDS.y) / PLAYFIELD_BOUNDS.h * 384.0;
         let mouse_pos = Point::new(pos_x as i32, pos_y as i32);
+        // where a new object would be placed, after distance snap
+        let placement_pos = self.distance_snapped(mouse_pos, self.snap_time(time));
+        let placement_pos = osu_to_screen(placement_pos.x as f32, placement_pos.y as f32);
+        self.draw_distance_snap(ctx, self.snap_time(time))?;
+        self.draw_transform_preview(ctx)?;
         match self.tool {
             Tool::Select => {
                 let (mx, my) = self.mouse_pos;
+                self.draw_selection_drag(ctx)?;
+                self.draw_anchor_drag(ctx)?;
+                self.draw_repeat_drag(ctx, time)?;
+                self.draw_timeline_drag(ctx, time)?;
+                self.draw_spacing_ratio(ctx, time)?;
                 if let Some((dx, dy)) = self.left_drag_start {
-                    if rect_contains(&PLAYFIELD_BOUNDS, dx, dy) {
+                    if rect_contains(&PLAYFIELD_BOUNDS, dx, dy)
+                        && self.selection_drag_anchor.is_none()
+                    {
                         let ax = dx.min(mx);
                         let ay = dy.min(my);
                         let bx = dx.max(mx);
@@ -268,7 +416,7 @@ impl Game {
             }
             Tool::Circle => {
                 if rect_contains(&PLAYFIELD_BOUNDS, mx, my) {
-                    let pos = [mx, my];
+                    let pos = placement_pos;
                     let color = Color::new(1.0, 1.0, 1.0, 0.4);
                     self.skin.hitcircle.draw(
                         ctx,
@@ -295,11 +443,13 @@ impl Game {
                     }
 
                     if nodes.len() > 1 && !(nodes.len() == 2 && nodes[0] == nodes[1]) {
-                        let slider_velocity =
-                            self.beatmap.inner.get_slider_velocity_at_time(time_millis);
+                        let slider_velocity = self
+                            .beatmap
+                            .inner
+                            .get_slider_velocity_at_time(state.start_time);
                         let slider_multiplier = self.beatmap.inner.difficulty.slider_multiplier;
                         let pixels_per_beat = slider_multiplier * 100.0 * slider_velocity;
-                        let pixels_per_tick = pixels_per_beat / 4.0; // TODO: FIX!!!
+                        let pixels_per_tick = pixels_per_beat / self.beat_divisor() as f64;
 
                         let mut spline = Spline::from_control(kind, &nodes, None);
                         let len = spline.pixel_length();
@@ -313,12 +463,14 @@ impl Game {
                         spline.truncate(fixed_len);
 
                         debug!("len: {}", spline.pixel_length());
+                        let (track_color, border_color) = self.slider_colors(color);
                         Game::render_spline(
                             ctx,
                             &self.beatmap.inner,
                             &spline,
                             PLAYFIELD_BOUNDS,
-                            color,
+                            track_color,
+                            border_color,
                         )?;
                         debug!("done rendering slider body");
                     }
@@ -327,7 +479,7 @@ impl Game {
                     debug!("done rendering slider wireframe");
                 } else {
                     if rect_contains(&PLAYFIELD_BOUNDS, mx, my) {
-                        let pos = [mx, my];
+                        let pos = placement_pos;
                         self.skin.hitcircle.draw(
                             ctx,
                             (cs_real * 2.0, cs_real * 2.0),
@@ -341,7 +493,29 @@ impl Game {
                     }
                 }
             }
-            _ => {}
+            Tool::Spinner => {
+                let color = Color::new(1.0, 1.0, 1.0, 0.4);
+                if let Some(state) = &self.partial_spinner_state {
+                    let end_time = self.partial_spinner_end_time(time);
+                    self.draw_timeline_range(
+                        ctx,
+                        time,
+                        state.start_time.as_seconds(),
+                        end_time.as_seconds(),
+                        color,
+                    )?;
+                }
+
+                if self.partial_spinner_state.is_some() || rect_contains(&PLAYFIELD_BOUNDS, mx, my)
+                {
+                    let size = PLAYFIELD_BOUNDS.h * SPINNER_SIZE;
+                    self.skin.spinnercircle.draw(
+                        ctx,
+                        (size, size),
+                        DrawParam::default().dest(spinner_screen_pos()).color(color),
+                    )?;
+                }
+            }
         }
 
         if let Some(mut state) = self.ui_state.take() {
@@ -353,6 +527,9 @@ impl Game {
         self.frame += 1;
         if self.is_playing {
             self.timestamp_changed()?;
+            if let Some(time) = self.song.as_ref().map(|song| song.position()).transpose()? {
+                self.update_hitsounds(time)?;
+            }
         }
 
         Ok(())
@@ -361,42 +538,82 @@ impl Game {
     fn timestamp_changed(&mut self) -> Result<()> {
         if let Some(song) = &self.song {
             let pos = song.position()?;
+            self.current_uninherited_timing_point =
+                self.beatmap.uninherited_timing_point_at(pos).cloned();
+            self.current_inherited_timing_point =
+                self.beatmap.inherited_timing_point_at(pos).cloned();
+        }
 
-            if let Some(timing_point) = self.beatmap.inner.timing_points.first() {
-                if pos < timing_point.time.as_seconds() {
-                    if let TimingPointKind::Uninherited(_) = &timing_point.kind {
-                        self.current_uninherited_timing_point = Some(timing_point.clone());
-                    }
-                }
-            }
+        Ok(())
+    }
 
-            let mut found_uninherited = false;
-            let mut found_inherited = false;
-            for timing_point in self.beatmap.inner.timing_points.iter() {
-                if pos < timing_point.time.as_seconds() {
-                    continue;
-                }
+    /// The beat snap divisor, i.e. how many ticks each beat is split into.
+    pub fn beat_divisor(&self) -> u32 {
+        match self.beatmap.inner.beat_divisor as u32 {
+            0 => 4,
+            divisor => divisor,
+        }
+    }
 
-                match &timing_point.kind {
-                    TimingPointKind::Uninherited(_) => {
-                        self.current_uninherited_timing_point = Some(timing_point.clone());
-                        found_uninherited = true;
-                    }
-                    TimingPointKind::Inherited(_) => {
-                        self.current_inherited_timing_point = Some(timing_point.clone());
-                        found_inherited = true;
-                    }
-                }
+    pub fn set_beat_divisor(&mut self, divisor: u32) {
+        self.beatmap.inner.beat_divisor = divisor as _;
+    }
 
-                if found_inherited && found_uninherited {
-                    break;
-                }
-            }
-        }
+    /// Moves to the next bigger (or, for negative steps, smaller) beat divisor.
+    pub fn step_beat_divisor(&mut self, steps: i32) {
+        self.set_beat_divisor(timeline::step_divisor(self.beat_divisor(), steps));
+    }
 
+    pub fn set_playback_rate(&mut self, rate: f64) -> Result<()> {
+        if let Some(song) = &mut self.song {
+            song.set_playback_rate(rate)?;
+        }
+        self.playback_rate = rate;
         Ok(())
     }
 
+    /// BPM of the timing point in effect right now, as it sounds at the current playback rate.
+    fn effective_bpm(&self) -> Option<f64> {
+        match &self.current_uninherited_timing_point {
+            Some(TimingPoint {
+                kind: TimingPointKind::Uninherited(info),
+                ..
+            }) if info.mpb > 0.0 => Some(60_000.0 / info.mpb * self.playback_rate),
+            _ => None,
+        }
+    }
+
+    /// Rounds a time (in seconds) to the nearest tick of the timing point in effect at that time.
+    fn snap_time(&self, time: f64) -> Millis {
+        if let Some(TimingPoint {
+            kind: TimingPointKind::Uninherited(info),
+            time: offset,
+            ..
+        }) = self.beatmap.uninherited_timing_point_at(time)
+        {
+            let offset = offset.as_seconds();
+            let tick = info.mpb / 1000.0 / self.beat_divisor() as f64;
+            let ticks = ((time - offset) / tick).round();
+            return Millis::from_seconds(offset + ticks * tick);
+        }
+
+        Millis::from_seconds(time)
+    }
+
+    /// Where the spinner being placed would end right now. Dragging on the timeline picks the end
+    /// time, otherwise it's wherever the song currently is.
+    fn partial_spinner_end_time(&self, time: f64) -> Millis {
+        use self::timeline::BOUNDS;
+
+        if let Some((dx, dy)) = self.left_drag_start {
+            if rect_contains(&BOUNDS, dx, dy) {
+                let (mx, _) = self.mouse_pos;
+                return self.snap_time(self.timeline_time_at(mx, time));
+            }
+        }
+        self.snap_time(time)
+    }
+
     fn seek_by_steps(&mut self, n: i32) -> Result<()> {
         if let Some(song) = &self.song {
             let pos = song.position()?;
@@ -408,7 +625,7 @@ impl Game {
             }) = &self.current_uninherited_timing_point
             {
                 let diff = pos - time.as_seconds();
-                let tick = info.mpb / 1000.0 / info.meter as f64;
+                let tick = info.mpb / 1000.0 / self.beat_divisor() as f64;
                 let beats = (diff / tick).round();
                 let frac = diff - beats * tick;
                 if frac.abs() < 0.0001 {
@@ -433,135 +650,385 @@ impl Game {
         let pos_y = (y - PLAYFIELD_BOUNDS.y) / PLAYFIELD_BOUNDS.h * 384.0;
         let pos = Point::new(pos_x as i32, pos_y as i32);
 
-        if let Some(song) = &self.song {
-            println!("song exists! {:?} {:?}", btn, self.tool);
-            let time = song.position()?;
-            let time_millis = Millis::from_seconds(time);
-
-            if let (MouseButton::Left, Tool::Select) = (btn, &self.tool) {
-            } else if let (MouseButton::Left, Tool::Circle) = (btn, &self.tool) {
-                println!("left, circle, {:?} {} {}", PLAYFIELD_BOUNDS, x, y);
-                if rect_contains(&PLAYFIELD_BOUNDS, x, y) {
-                    let time = Millis::from_seconds(song.position()?);
-                    match self
-                        .beatmap
-                        .hit_objects
-                        .binary_search_by_key(&time, |ho| ho.inner.start_time)
-                    {
-                        Ok(v) => {
-                            println!("unfortunately already found at idx {}", v);
-                        }
-                        Err(idx) => {
-                            use libosu::{
-                                hitobject::HitObject,
-                                hitsounds::{Additions, SampleInfo},
-                            };
-
-                            let inner = HitObject {
-                                start_time: time,
-                                pos,
-                                kind: HitObjectKind::Circle,
-                                new_combo: false,
-                                skip_color: 0,
-                                additions: Additions::empty(),
-                                sample_info: SampleInfo::default(),
-                            };
-
-                            let new_obj = HitObjectExt {
-                                inner,
-                                stacking: 0,
-                                color_idx: 0,
-                                number: 0,
-                            };
-                            println!("creating new hitobject: {:?}", new_obj);
-                            self.beatmap.hit_objects.insert(idx, new_obj);
-                            self.beatmap.compute_stacking();
-                            self.beatmap.compute_colors(&self.combo_colors);
-                        }
+        let time = match &self.song {
+            Some(song) => song.position()?,
+            None => return Ok(()),
+        };
+        println!("song exists! {:?} {:?}", btn, self.tool);
+        let time_millis = self.snap_time(time);
+
+        if btn == MouseButton::Left
+            && rect_contains(&timeline::BOUNDS, x, y)
+            && self.partial_spinner_state.is_none()
+        {
+            return self.click_timeline(x, time);
+        }
+
+        if let (MouseButton::Left, Tool::Select) = (btn, &self.tool) {
+            // ctrl+click on the selected slider's body adds an anchor, and anywhere else it adds
+            // to the selection like it usually does
+            let ctrl = self.keymap.contains(&KeyCode::LControl)
+                || self.keymap.contains(&KeyCode::RControl);
+            // clicking on an anchor of the selected slider shouldn't change the selection
+            if rect_contains(&PLAYFIELD_BOUNDS, x, y)
+                && self.anchor_drag.is_none()
+                && !(ctrl && self.insert_anchor_at(x, y))
+            {
+                self.select_at(x, y, time);
+            }
+        } else if let (MouseButton::Right, Tool::Select) = (btn, &self.tool) {
+            // shift turns anchors red instead of deleting them
+            if self.keymap.contains(&KeyCode::LShift) || self.keymap.contains(&KeyCode::RShift) {
+                self.toggle_red_anchor_at(x, y);
+            } else {
+                self.delete_anchor_at(x, y);
+            }
+        } else if let (MouseButton::Left, Tool::Circle) = (btn, &self.tool) {
+            println!("left, circle, {:?} {} {}", PLAYFIELD_BOUNDS, x, y);
+            if rect_contains(&PLAYFIELD_BOUNDS, x, y) {
+                match self
+                    .beatmap
+                    .hit_objects
+                    .binary_search_by_key(&time_millis, |ho| ho.inner.start_time)
+                {
+                    Ok(v) => {
+                        println!("unfortunately already found at idx {}", v);
+                    }
+                    Err(idx) => {
+                        use libosu::{
+                            hitobject::HitObject,
+                            hitsounds::{Additions, SampleInfo},
+                        };
+
+                        let inner = HitObject {
+                            start_time: time_millis,
+                            pos: self.distance_snapped(pos, time_millis),
+                            kind: HitObjectKind::Circle,
+                            new_combo: false,
+                            skip_color: 0,
+                            additions: Additions::empty(),
+                            sample_info: SampleInfo::default(),
+                        };
+
+                        println!("creating new hitobject: {:?}", inner);
+                        let mut command = Command::new("Place circle");
+                        command.push(Change::InsertHitObject {
+                            index: idx,
+                            object: inner,
+                        });
+                        self.execute(command);
                     }
                 }
-            } else if let (MouseButton::Left, Tool::Slider) = (btn, &self.tool) {
-                if let Some(PartialSliderState {
-                    kind,
-                    control_points: ref mut nodes,
-                    ..
-                }) = &mut self.partial_slider_state
+            }
+        } else if let (MouseButton::Left, Tool::Slider) = (btn, &self.tool) {
+            // only the head of the slider is distance snapped
+            let head_pos = self.distance_snapped(pos, time_millis);
+            if let Some(PartialSliderState {
+                kind,
+                control_points: ref mut nodes,
+                ..
+            }) = &mut self.partial_slider_state
+            {
+                nodes.push(pos);
+                *kind = upgrade_slider_type(*kind, nodes.len());
+            } else {
+                self.partial_slider_state = Some(PartialSliderState {
+                    start_time: time_millis,
+                    kind: SliderSplineKind::Linear,
+                    control_points: vec![head_pos],
+                    pixel_length: 0.0,
+                });
+            }
+        } else if let (MouseButton::Left, Tool::Spinner) = (btn, &self.tool) {
+            if self.partial_spinner_state.is_some() {
+                let end_time = self.snap_time(time);
+                self.place_spinner(end_time);
+            } else if rect_contains(&PLAYFIELD_BOUNDS, x, y) {
+                self.partial_spinner_state = Some(PartialSpinnerState {
+                    start_time: self.snap_time(time),
+                });
+            }
+        } else if let (MouseButton::Right, Tool::Spinner) = (btn, &self.tool) {
+            self.partial_spinner_state = None;
+        } else if let (MouseButton::Right, Tool::Slider) = (btn, &self.tool) {
+            if let Some(mut state) = self.partial_slider_state.take() {
+                match self
+                    .beatmap
+                    .hit_objects
+                    .binary_search_by_key(&state.start_time.0, |ho| ho.inner.start_time.0)
                 {
-                    nodes.push(pos);
-                    *kind = upgrade_slider_type(*kind, nodes.len());
-                } else {
-                    self.partial_slider_state = Some(PartialSliderState {
-                        start_time: time_millis,
-                        kind: SliderSplineKind::Linear,
-                        control_points: vec![pos],
-                        pixel_length: 0.0,
-                    });
-                }
-            } else if let (MouseButton::Right, Tool::Slider) = (btn, &self.tool) {
-                if let Some(state) = &mut self.partial_slider_state {
-                    match self
-                        .beatmap
-                        .hit_objects
-                        .binary_search_by_key(&state.start_time.0, |ho| ho.inner.start_time.0)
-                    {
-                        Ok(v) => {
-                            println!("unfortunately already found at idx {}", v);
-                        }
-                        Err(idx) => {
-                            use libosu::{
-                                hitobject::{HitObject, SliderInfo},
-                                hitsounds::{Additions, SampleInfo},
-                            };
-
-                            state.control_points.push(pos);
-                            let after_len = state.control_points.len();
-                            let first = state.control_points.remove(0);
-                            let inner = HitObject {
-                                start_time: state.start_time,
-                                pos: first,
-                                kind: HitObjectKind::Slider(SliderInfo {
-                                    kind: upgrade_slider_type(state.kind, after_len),
-                                    control_points: state.control_points.clone(),
-                                    num_repeats: 1,
-                                    pixel_length: state.pixel_length,
-                                    edge_additions: vec![],
-                                    edge_samplesets: vec![],
-                                }),
-                                new_combo: false,
-                                skip_color: 0,
-                                additions: Additions::empty(),
-                                sample_info: SampleInfo::default(),
-                            };
-
-                            let new_obj = HitObjectExt {
-                                inner,
-                                stacking: 0,
-                                color_idx: 0,
-                                number: 0,
-                            };
-                            println!("creating new hitobject: {:?}", new_obj);
-                            self.beatmap.hit_objects.insert(idx, new_obj);
-                            self.beatmap.compute_stacking();
-                            self.beatmap.compute_colors(&self.combo_colors);
-                        }
+                    Ok(v) => {
+                        println!("unfortunately already found at idx {}", v);
+                    }
+                    Err(idx) => {
+                        use libosu::{
+                            hitobject::{HitObject, SliderInfo},
+                            hitsounds::{Additions, SampleInfo},
+                        };
+
+                        state.control_points.push(pos);
+                        let after_len = state.control_points.len();
+                        let first = state.control_points.remove(0);
+                        let inner = HitObject {
+                            start_time: state.start_time,
+                            pos: first,
+                            kind: HitObjectKind::Slider(SliderInfo {
+                                kind: upgrade_slider_type(state.kind, after_len),
+                                control_points: state.control_points.clone(),
+                                num_repeats: 1,
+                                pixel_length: state.pixel_length,
+                                edge_additions: vec![],
+                                edge_samplesets: vec![],
+                            }),
+                            new_combo: false,
+                            skip_color: 0,
+                            additions: Additions::empty(),
+                            sample_info: SampleInfo::default(),
+                        };
+
+                        println!("creating new hitobject: {:?}", inner);
+                        let mut command = Command::new("Place slider");
+                        command.push(Change::InsertHitObject {
+                            index: idx,
+                            object: inner,
+                        });
+                        self.execute(command);
                     }
-                    self.partial_slider_state = None;
                 }
             }
         }
         Ok(())
     }
 
+    /// Handles the mouse being dragged from one point to another and then released.
+    fn handle_drag(
+        &mut self,
+        btn: MouseButton,
+        (start_x, start_y): (f32, f32),
+        (x, y): (f32, f32),
+    ) -> Result<()> {
+        let time = match &self.song {
+            Some(song) => song.position()?,
+            None => return Ok(()),
+        };
+
+        if let (MouseButton::Left, Some(_)) = (btn, self.repeat_drag) {
+            self.finish_repeat_drag(time);
+        } else if let (MouseButton::Left, Some(_)) = (btn, self.timeline_drag) {
+            self.finish_timeline_drag(time)?;
+        } else if let (MouseButton::Left, Tool::Spinner) = (btn, &self.tool) {
+            use self::timeline::BOUNDS;
+
+            if self.partial_spinner_state.is_some() && rect_contains(&BOUNDS, start_x, start_y) {
+                let end_time = self.snap_time(self.timeline_time_at(x, time));
+                self.place_spinner(end_time);
+            }
+        } else if let (MouseButton::Left, Tool::Select) = (btn, &self.tool) {
+            if self.anchor_drag.is_some() {
+                self.finish_anchor_drag();
+            } else if self.selection_drag_anchor.is_some() {
+                self.finish_selection_drag();
+            } else if rect_contains(&PLAYFIELD_BOUNDS, start_x, start_y) {
+                let ax = start_x.min(x);
+                let ay = start_y.min(y);
+                let bx = start_x.max(x);
+                let by = start_y.max(y);
+                self.select_in_rect(Rect::new(ax, ay, bx - ax, by - ay), time);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes placing the spinner that was started with the spinner tool.
+    fn place_spinner(&mut self, end_time: Millis) {
+        use libosu::{
+            hitobject::{HitObject, SpinnerInfo},
+            hitsounds::{Additions, SampleInfo},
+        };
+
+        let start_time = match &self.partial_spinner_state {
+            Some(state) => state.start_time,
+            None => return,
+        };
+        if end_time.0 <= start_time.0 {
+            warn!("spinner would end before it starts, ignoring");
+            return;
+        }
+        self.partial_spinner_state = None;
+
+        match self
+            .beatmap
+            .hit_objects
+            .binary_search_by_key(&start_time.0, |ho| ho.inner.start_time.0)
+        {
+            Ok(v) => {
+                warn!(
+                    "there is already a hit object at {}ms (idx {})",
+                    start_time.0, v
+                );
+            }
+            Err(idx) => {
+                let inner = HitObject {
+                    start_time,
+                    pos: Point::new(256, 192),
+                    kind: HitObjectKind::Spinner(SpinnerInfo { end_time }),
+                    new_combo: true,
+                    skip_color: 0,
+                    additions: Additions::empty(),
+                    sample_info: SampleInfo::default(),
+                };
+
+                debug!("placing spinner: {:?}", inner);
+                let mut command = Command::new("Place spinner");
+                command.push(Change::InsertHitObject {
+                    index: idx,
+                    object: inner,
+                });
+                self.execute(command);
+            }
+        }
+    }
+
+    /// Applies a command to the beatmap and records it in the history so it can be undone.
+    fn execute(&mut self, command: Command) {
+        if command.is_empty() {
+            return;
+        }
+
+        debug!("executing command: {:?}", command);
+        if command.reorders_hit_objects() {
+            self.selected_objects.clear();
+            self.hitsound_edge = None;
+        }
+        command.apply(&mut self.beatmap);
+        self.refresh(
+            command.affects_hit_objects(),
+            command.affects_timing_points(),
+        );
+        self.history.push(command);
+    }
+
+    fn undo(&mut self) {
+        let (hit_objects, timing_points) = match self.history.undo(&mut self.beatmap) {
+            Some(command) => (
+                command.affects_hit_objects(),
+                command.affects_timing_points(),
+            ),
+            None => return,
+        };
+
+        self.selected_objects.clear();
+        self.hitsound_edge = None;
+        self.refresh(hit_objects, timing_points);
+    }
+
+    fn redo(&mut self) {
+        let (hit_objects, timing_points) = match self.history.redo(&mut self.beatmap) {
+            Some(command) => (
+                command.affects_hit_objects(),
+                command.affects_timing_points(),
+            ),
+            None => return,
+        };
+
+        self.selected_objects.clear();
+        self.hitsound_edge = None;
+        self.refresh(hit_objects, timing_points);
+    }
+
+    /// Recomputes whatever depends on the parts of the beatmap that were changed.
+    fn refresh(&mut self, hit_objects: bool, timing_points: bool) {
+        if hit_objects {
+            self.refresh_hit_objects();
+        }
+        if timing_points {
+            if let Err(err) = self.timestamp_changed() {
+                error!("failed to update current timing point: {}", err);
+            }
+        }
+    }
+
+    /// Recomputes everything derived from the list of hit objects.
+    fn refresh_hit_objects(&mut self) {
+        self.beatmap.compute_stacking();
+        self.update_combo_colors();
+        self.beatmap.compute_colors(&self.combo_colors);
+    }
+
+    /// Picks up the beatmap's combo colors (or the ones being previewed), or the default ones if
+    /// it doesn't have any.
+    fn update_combo_colors(&mut self) {
+        self.combo_colors = match &self.color_preview {
+            Some(preview) => preview
+                .combo_colors
+                .iter()
+                .map(|[r, g, b]| Color::new(*r, *g, *b, 1.0))
+                .collect(),
+            None => self
+                .beatmap
+                .inner
+                .colors
+                .iter()
+                .map(|color| {
+                    Color::new(
+                        color.red as f32 / 255.0,
+                        color.green as f32 / 255.0,
+                        color.blue as f32 / 255.0,
+                        1.0,
+                    )
+                })
+                .collect(),
+        };
+        if self.combo_colors.is_empty() {
+            self.combo_colors = DEFAULT_COLORS
+                .iter()
+                .map(|(r, g, b)| Color::new(*r, *g, *b, 1.0))
+                .collect();
+        }
+    }
+
+    /// The track and border colors of a slider with the given combo color, taking the beatmap's
+    /// slider color settings (or the ones being previewed) into account. Both keep the combo
+    /// color's alpha.
+    fn slider_colors(&self, combo_color: Color) -> (Color, Color) {
+        let (track, border) = match &self.color_preview {
+            Some(preview) => (preview.slider_track_override, preview.slider_border),
+            None => (
+                self.beatmap.slider_track_override.map(rgb_to_f32),
+                self.beatmap.slider_border.map(rgb_to_f32),
+            ),
+        };
+        let to_color = |[r, g, b]: [f32; 3]| Color::new(r, g, b, combo_color.a);
+        let track = track.map(to_color).unwrap_or(combo_color);
+        let mut border = border.map(to_color).unwrap_or(Color::WHITE);
+        border.a = combo_color.a;
+        (track, border)
+    }
+
     fn switch_tool_to(&mut self, target: Tool) {
         // clear slider state if we're switching away from slider
         if matches!(self.tool, Tool::Slider) && !matches!(target, Tool::Slider) {
             self.partial_slider_state = None;
         }
+        if matches!(self.tool, Tool::Spinner) && !matches!(target, Tool::Spinner) {
+            self.partial_spinner_state = None;
+        }
 
         self.tool = target;
     }
 }
 
+/// Size of a spinner, relative to the height of the playfield.
+pub const SPINNER_SIZE: f32 = 0.6;
+
+/// Spinners are always drawn in the middle of the playfield.
+pub fn spinner_screen_pos() -> [f32; 2] {
+    [
+        PLAYFIELD_BOUNDS.x + PLAYFIELD_BOUNDS.w / 2.0,
+        PLAYFIELD_BOUNDS.y + PLAYFIELD_BOUNDS.h / 2.0,
+    ]
+}
+
 fn upgrade_slider_type(initial_type: SliderSplineKind, after_len: usize) -> SliderSplineKind {
     match (initial_type, after_len) {
         (SliderSplineKind::Linear, 3) => SliderSplineKind::Perfect,
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use ggez::{graphics::Color, Context};
+use libosu::hitobject::{HitObject, HitObjectKind};
+
+use crate::history::{Change, Command};
+use crate::utils::rect_contains;
+
+use super::timeline::BOUNDS;
+use super::{Game, Tool};
+
+/// How close (in screen pixels) the mouse has to be to the end of a slider on the timeline to
+/// grab it.
+const TAIL_GRAB_DISTANCE: f32 = 8.0;
+
+/// The slider with a different number of repeats. Sliders that have their own hitsounds for each
+/// edge get hitsounds for the new edges from the whole slider.
+fn with_repeats(ho: &HitObject, num_repeats: u32) -> HitObject {
+    let mut ho = ho.clone();
+    let additions = ho.additions;
+    let sets = (ho.sample_info.sample_set, ho.sample_info.addition_set);
+    if let HitObjectKind::Slider(info) = &mut ho.kind {
+        info.num_repeats = num_repeats as _;
+        let edges = num_repeats as usize + 1;
+        if !info.edge_additions.is_empty() {
+            info.edge_additions.resize(edges, additions);
+        }
+        if !info.edge_samplesets.is_empty() {
+            info.edge_samplesets.resize(edges, sets);
+        }
+    }
+    ho
+}
+
+impl Game {
+    /// How long it takes to go from one end of a slider to the other, in seconds.
+    fn slider_span_duration(&self, ho: &HitObject) -> Option<f64> {
+        match &ho.kind {
+            HitObjectKind::Slider(info) => {
+                let duration = self.beatmap.inner.get_slider_duration(ho)?;
+                Some(duration / info.num_repeats.max(1) as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Starts dragging the end of a slider on the timeline, if the mouse was pressed on one.
+    /// Selected sliders are picked over the others. Returns whether one was grabbed.
+    pub(super) fn begin_repeat_drag(&mut self, x: f32, y: f32, time: f64) -> bool {
+        if !matches!(self.tool, Tool::Select) || !rect_contains(&BOUNDS, x, y) {
+            return false;
+        }
+
+        let is_grabbed = |idx: usize| {
+            let ho = &self.beatmap.hit_objects[idx].inner;
+            if !ho.kind.is_slider() {
+                return false;
+            }
+            match self.beatmap.inner.get_hitobject_end_time(ho) {
+                Some(end_time) => {
+                    (self.timeline_x_at(end_time, time) - x).abs() <= TAIL_GRAB_DISTANCE
+                }
+                None => false,
+            }
+        };
+        let grabbed = self
+            .selected_objects
+            .iter()
+            .cloned()
+            .find(|idx| is_grabbed(*idx))
+            .or_else(|| (0..self.beatmap.hit_objects.len()).find(|idx| is_grabbed(*idx)));
+
+        self.repeat_drag = grabbed;
+        grabbed.is_some()
+    }
+
+    /// The slider being dragged, and how many repeats it would have if the drag ended right now.
+    fn dragged_repeats(&self, time: f64) -> Option<(usize, u32)> {
+        let idx = self.repeat_drag?;
+        let ho = &self.beatmap.hit_objects.get(idx)?.inner;
+        let span_duration = self.slider_span_duration(ho)?;
+        if span_duration <= 0.0 {
+            return None;
+        }
+
+        let (mx, _) = self.mouse_pos;
+        let end_time = self.timeline_time_at(mx, time);
+        let spans = ((end_time - ho.start_time.as_seconds()) / span_duration).round();
+        Some((idx, spans.max(1.0) as u32))
+    }
+
+    pub(super) fn finish_repeat_drag(&mut self, time: f64) {
+        if let Some((idx, num_repeats)) = self.dragged_repeats(time) {
+            let before = self.beatmap.hit_objects[idx].inner.clone();
+            let changed = match &before.kind {
+                HitObjectKind::Slider(info) => info.num_repeats as u32 != num_repeats,
+                _ => false,
+            };
+            if changed {
+                let after = with_repeats(&before, num_repeats);
+                let mut command = Command::new("Change repeats");
+                command.push(Change::ModifyHitObject {
+                    index: idx,
+                    before,
+                    after,
+                });
+                self.execute(command);
+            }
+        }
+        self.repeat_drag = None;
+    }
+
+    /// Shows how long the slider being dragged would be on the timeline.
+    pub(super) fn draw_repeat_drag(&self, ctx: &mut Context, time: f64) -> Result<()> {
+        let (idx, num_repeats) = match self.dragged_repeats(time) {
+            Some(dragged) => dragged,
+            None => return Ok(()),
+        };
+
+        let ho = &self.beatmap.hit_objects[idx].inner;
+        let span_duration = self.slider_span_duration(ho).unwrap_or(0.0);
+        let start_time = ho.start_time.as_seconds();
+        let end_time = start_time + span_duration * num_repeats as f64;
+        self.draw_timeline_range(
+            ctx,
+            time,
+            start_time,
+            end_time,
+            Color::new(1.0, 1.0, 1.0, 0.3),
+        )
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::Result;
+use ggez::{
+    event::KeyCode,
+    graphics::{Color, DrawParam, Rect},
+    Context,
+};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    math::Point,
+};
+
+use crate::beatmap::STACK_DISTANCE;
+use crate::history::{Change, Command};
+use crate::utils::rect_contains;
+
+use super::{spinner_screen_pos, Game, PLAYFIELD_BOUNDS, SPINNER_SIZE};
+
+impl Game {
+    /// Whether shift or ctrl is held, which makes selections add to the existing selection
+    /// instead of replacing it.
+    pub(super) fn is_additive_selection(&self) -> bool {
+        [
+            KeyCode::LShift,
+            KeyCode::RShift,
+            KeyCode::LControl,
+            KeyCode::RControl,
+        ]
+        .iter()
+        .any(|key| self.keymap.contains(key))
+    }
+
+    /// Indices of the hit objects that are currently visible on the playfield, earliest first.
+    pub(super) fn visible_hitobjects(&self, time: f64) -> Vec<usize> {
+        // keep this in sync with the visibility window in draw_hitobjects
+        let preempt = 1.5
+            * self
+                .beatmap
+                .inner
+                .difficulty
+                .approach_preempt()
+                .as_seconds();
+        let fade_out_time = 0.75;
+
+        self.beatmap
+            .hit_objects
+            .iter()
+            .enumerate()
+            .filter(|(_, ho)| {
+                let start_time = ho.inner.start_time.as_seconds();
+                let end_time = self
+                    .beatmap
+                    .inner
+                    .get_hitobject_end_time(&ho.inner)
+                    .unwrap();
+                start_time - preempt <= time && time <= end_time + fade_out_time
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Screen position of the head of the given hit object, after stacking is applied.
+    pub(super) fn stacked_screen_pos(&self, idx: usize) -> [f32; 2] {
+        let ho = &self.beatmap.hit_objects[idx];
+        let stacking = ho.stacking as f32 * STACK_DISTANCE as f32;
+        osu_to_screen(
+            ho.inner.pos.x as f32 - stacking,
+            ho.inner.pos.y as f32 - stacking,
+        )
+    }
+
+    /// Screen position of the tail of the given slider, with the same stacking offset as its head.
+    pub(super) fn stacked_end_screen_pos(&self, idx: usize) -> [f32; 2] {
+        let ho = &self.beatmap.hit_objects[idx];
+        let stacking = ho.stacking as f32 * STACK_DISTANCE as f32;
+        let end_pos = ho.inner.end_pos();
+        osu_to_screen(end_pos.x as f32 - stacking, end_pos.y as f32 - stacking)
+    }
+
+    /// Finds the topmost visible hit object under the given screen position.
+    pub(super) fn hitobject_at(&self, x: f32, y: f32, time: f64) -> Option<usize> {
+        let cs_scale = PLAYFIELD_BOUNDS.w / 640.0;
+        let cs_real = self.beatmap.inner.difficulty.circle_size_osupx() * cs_scale;
+        let within = |pos: [f32; 2]| {
+            let (dx, dy) = (pos[0] - x, pos[1] - y);
+            dx * dx + dy * dy <= cs_real * cs_real
+        };
+
+        // earlier objects are drawn on top of later ones
+        for idx in self.visible_hitobjects(time) {
+            let ho = &self.beatmap.hit_objects[idx];
+            if ho.inner.kind.is_spinner() {
+                let center = spinner_screen_pos();
+                let radius = PLAYFIELD_BOUNDS.h * SPINNER_SIZE / 2.0;
+                let (dx, dy) = (center[0] - x, center[1] - y);
+                if dx * dx + dy * dy <= radius * radius {
+                    return Some(idx);
+                }
+                continue;
+            }
+
+            if within(self.stacked_screen_pos(idx)) {
+                return Some(idx);
+            }
+
+            if let HitObjectKind::Slider(_) = &ho.inner.kind {
+                if within(self.stacked_end_screen_pos(idx)) {
+                    return Some(idx);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Handles a single click on the playfield with the select tool.
+    pub(super) fn select_at(&mut self, x: f32, y: f32, time: f64) {
+        let additive = self.is_additive_selection();
+        match self.hitobject_at(x, y, time) {
+            Some(idx) if additive => {
+                if let Some(pos) = self.selected_objects.iter().position(|i| *i == idx) {
+                    self.selected_objects.remove(pos);
+                } else {
+                    self.selected_objects.push(idx);
+                }
+            }
+            Some(idx) => self.selected_objects = vec![idx],
+            None if additive => {}
+            None => self.selected_objects.clear(),
+        }
+    }
+
+    /// Selects every visible hit object whose head lies inside the given screen rectangle.
+    pub(super) fn select_in_rect(&mut self, rect: Rect, time: f64) {
+        if !self.is_additive_selection() {
+            self.selected_objects.clear();
+        }
+
+        for idx in self.visible_hitobjects(time) {
+            let [x, y] = self.stacked_screen_pos(idx);
+            if rect_contains(&rect, x, y) && !self.selected_objects.contains(&idx) {
+                self.selected_objects.push(idx);
+            }
+        }
+    }
+
+    /// Starts dragging the selection if the mouse was pressed on top of an object.
+    pub(super) fn begin_selection_drag(&mut self, x: f32, y: f32, time: f64) {
+        if self.is_additive_selection() {
+            return;
+        }
+
+        if let Some(idx) = self.hitobject_at(x, y, time) {
+            if !self.selected_objects.contains(&idx) {
+                self.selected_objects = vec![idx];
+            }
+            self.selection_drag_anchor = Some(idx);
+        }
+    }
+
+    /// How far (in osu!pixels) the selection would be moved if the drag ended right now.
+    ///
+    /// The object that was grabbed is snapped to the grid, and the offset is limited so that
+    /// nothing gets pushed out of the playfield.
+    pub(super) fn selection_drag_offset(&self) -> Option<(i32, i32)> {
+        let anchor = self.selection_drag_anchor?;
+        let (mx, my) = self.mouse_pos;
+        let (start_x, start_y) = self.left_drag_start?;
+        if (mx, my) == (start_x, start_y) {
+            return None;
+        }
+
+        let anchor_pos = self.beatmap.hit_objects[anchor].inner.pos;
+        let grid_size = self.beatmap.inner.grid_size as i32;
+        let dx = (mx - start_x) / PLAYFIELD_BOUNDS.w * 512.0;
+        let dy = (my - start_y) / PLAYFIELD_BOUNDS.h * 384.0;
+        let target_x = snap_to(anchor_pos.x as f32 + dx, grid_size);
+        let target_y = snap_to(anchor_pos.y as f32 + dy, grid_size);
+        let (mut dx, mut dy) = (target_x - anchor_pos.x, target_y - anchor_pos.y);
+
+        for idx in self.selected_objects.iter() {
+            for point in object_points(&self.beatmap.hit_objects[*idx].inner) {
+                dx = dx.max(-point.x).min(512 - point.x);
+                dy = dy.max(-point.y).min(384 - point.y);
+            }
+        }
+
+        if dx == 0 && dy == 0 {
+            None
+        } else {
+            Some((dx, dy))
+        }
+    }
+
+    pub(super) fn finish_selection_drag(&mut self) {
+        if let Some((dx, dy)) = self.selection_drag_offset() {
+            self.move_selection(dx, dy);
+        }
+        self.selection_drag_anchor = None;
+    }
+
+    pub(super) fn move_selection(&mut self, dx: i32, dy: i32) {
+        let mut command = Command::new("Move");
+        for idx in self.selected_objects.iter() {
+            // spinners are always in the middle of the playfield
+            if self.beatmap.hit_objects[*idx].inner.kind.is_spinner() {
+                continue;
+            }
+
+            let before = self.beatmap.hit_objects[*idx].inner.clone();
+            let mut after = before.clone();
+            after.pos = Point::new(after.pos.x + dx, after.pos.y + dy);
+            if let HitObjectKind::Slider(info) = &mut after.kind {
+                for point in info.control_points.iter_mut() {
+                    *point = Point::new(point.x + dx, point.y + dy);
+                }
+            }
+
+            command.push(Change::ModifyHitObject {
+                index: *idx,
+                before,
+                after,
+            });
+        }
+        self.execute(command);
+    }
+
+    /// Changes each of the selected objects as one step that can be undone.
+    pub(super) fn edit_selection(&mut self, name: &str, mut f: impl FnMut(&mut HitObject)) {
+        let mut command = Command::new(name);
+        for idx in self.selected_objects.iter() {
+            let before = self.beatmap.hit_objects[*idx].inner.clone();
+            let mut after = before.clone();
+            f(&mut after);
+            command.push(Change::ModifyHitObject {
+                index: *idx,
+                before,
+                after,
+            });
+        }
+        self.execute(command);
+    }
+
+    pub(super) fn delete_selection(&mut self) {
+        let mut selected = self.selected_objects.clone();
+        selected.sort_unstable();
+
+        // remove from the back so the indices of the remaining objects stay the same
+        let mut command = Command::new("Delete");
+        for idx in selected.into_iter().rev() {
+            command.push(Change::RemoveHitObject {
+                index: idx,
+                object: self.beatmap.hit_objects[idx].inner.clone(),
+            });
+        }
+        self.execute(command);
+        self.selected_objects.clear();
+    }
+
+    /// Draws where the selected objects will end up while they're being dragged.
+    pub(super) fn draw_selection_drag(&self, ctx: &mut Context) -> Result<()> {
+        let (dx, dy) = match self.selection_drag_offset() {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+
+        let cs_scale = PLAYFIELD_BOUNDS.w / 640.0;
+        let cs_real = self.beatmap.inner.difficulty.circle_size_osupx() * cs_scale;
+        let color = Color::new(1.0, 1.0, 1.0, 0.5);
+        for idx in self.selected_objects.iter() {
+            let ho = &self.beatmap.hit_objects[*idx];
+            if ho.inner.kind.is_spinner() {
+                continue;
+            }
+
+            if let HitObjectKind::Slider(info) = &ho.inner.kind {
+                let control_points = std::iter::once(&ho.inner.pos)
+                    .chain(info.control_points.iter())
+                    .map(|point| Point::new(point.x + dx, point.y + dy))
+                    .collect::<Vec<_>>();
+                Game::render_slider_wireframe(ctx, &control_points, PLAYFIELD_BOUNDS, color)?;
+            }
+
+            let pos = osu_to_screen((ho.inner.pos.x + dx) as f32, (ho.inner.pos.y + dy) as f32);
+            self.skin.hitcircleselect.draw(
+                ctx,
+                (cs_real * 2.0, cs_real * 2.0),
+                DrawParam::default().dest(pos).color(color),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn osu_to_screen(x: f32, y: f32) -> [f32; 2] {
+    [
+        PLAYFIELD_BOUNDS.x + x * PLAYFIELD_BOUNDS.w / 512.0,
+        PLAYFIELD_BOUNDS.y + y * PLAYFIELD_BOUNDS.h / 384.0,
+    ]
+}
+
+pub(super) fn snap_to(value: f32, grid_size: i32) -> i32 {
+    (value / grid_size as f32).round() as i32 * grid_size
+}
+
+/// All of the points that make up the shape of a hit object.
+pub(super) fn object_points(ho: &libosu::hitobject::HitObject) -> Vec<Point<i32>> {
+    let mut points = vec![ho.pos];
+    if let HitObjectKind::Slider(info) = &ho.kind {
+        points.extend(info.control_points.iter().cloned());
+    }
+    points
+}
@@ -0,0 +1,365 @@
+use anyhow::Result;
+use ggez::{graphics::Color, Context};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind, SliderSplineKind},
+    math::Point,
+    spline::Spline,
+    timing::Millis,
+};
+
+use crate::history::{Change, Command};
+
+use super::select::{osu_to_screen, snap_to};
+use super::{Game, Tool, PLAYFIELD_BOUNDS};
+
+/// How close (in screen pixels) the mouse has to be to an anchor to grab it.
+const ANCHOR_RADIUS: f32 = 8.0;
+
+/// Spline kinds in the order they're shown in the UI.
+pub const SLIDER_KINDS: &[(SliderSplineKind, &str)] = &[
+    (SliderSplineKind::Linear, "Linear"),
+    (SliderSplineKind::Perfect, "Perfect Circle"),
+    (SliderSplineKind::Bezier, "Bezier"),
+    (SliderSplineKind::Catmull, "Catmull"),
+];
+
+/// An anchor of the selected slider that's being dragged around.
+#[derive(Clone, Copy, Debug)]
+pub struct AnchorDrag {
+    object: usize,
+    anchor: usize,
+}
+
+/// The head of a slider followed by its control points.
+fn slider_anchors(ho: &HitObject) -> Option<Vec<Point<i32>>> {
+    match &ho.kind {
+        HitObjectKind::Slider(info) => Some(
+            std::iter::once(ho.pos)
+                .chain(info.control_points.iter().cloned())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Perfect circles only work with exactly 3 anchors, so they become bezier curves otherwise.
+fn fix_slider_kind(kind: SliderSplineKind, anchors: usize) -> SliderSplineKind {
+    match kind {
+        SliderSplineKind::Perfect if anchors != 3 => SliderSplineKind::Bezier,
+        kind => kind,
+    }
+}
+
+/// Distance from `p` to the segment between `a` and `b`.
+fn distance_to_segment(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let len_squared = dx * dx + dy * dy;
+    let t = if len_squared > 0.0 {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / len_squared)
+            .max(0.0)
+            .min(1.0)
+    } else {
+        0.0
+    };
+    let (cx, cy) = (a[0] + t * dx, a[1] + t * dy);
+    ((p[0] - cx).powi(2) + (p[1] - cy).powi(2)).sqrt()
+}
+
+fn screen_to_osu(x: f32, y: f32) -> Point<i32> {
+    let x = (x - PLAYFIELD_BOUNDS.x) / PLAYFIELD_BOUNDS.w * 512.0;
+    let y = (y - PLAYFIELD_BOUNDS.y) / PLAYFIELD_BOUNDS.h * 384.0;
+    Point::new(x.round() as i32, y.round() as i32)
+}
+
+impl Game {
+    /// The slider whose anchors can be edited: the only selected object, if it's a slider.
+    fn edited_slider(&self) -> Option<usize> {
+        if !matches!(self.tool, Tool::Select) {
+            return None;
+        }
+        match self.selected_objects.as_slice() {
+            [idx] if self.beatmap.hit_objects[*idx].inner.kind.is_slider() => Some(*idx),
+            _ => None,
+        }
+    }
+
+    /// The anchor of the edited slider under the given screen position.
+    fn anchor_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let idx = self.edited_slider()?;
+        let anchors = slider_anchors(&self.beatmap.hit_objects[idx].inner)?;
+        // anchors that are drawn on top of each other (red anchors) are grabbed by the last one
+        anchors
+            .iter()
+            .rposition(|point| {
+                let [ax, ay] = osu_to_screen(point.x as f32, point.y as f32);
+                (ax - x).powi(2) + (ay - y).powi(2) <= ANCHOR_RADIUS.powi(2)
+            })
+            .map(|anchor| (idx, anchor))
+    }
+
+    /// Length of a slider with the given shape, rounded down to a whole number of beat snap
+    /// ticks (but at least one).
+    fn snapped_slider_length(
+        &self,
+        start_time: Millis,
+        kind: SliderSplineKind,
+        anchors: &[Point<i32>],
+    ) -> f64 {
+        let len = Spline::from_control(kind, anchors, None).pixel_length();
+        self.snap_slider_length(start_time, len)
+    }
+
+    /// Rounds a slider length down to a whole number of beat snap ticks (but at least one), so
+    /// the slider ends on a tick.
+    pub(super) fn snap_slider_length(&self, start_time: Millis, len: f64) -> f64 {
+        let slider_velocity = self.beatmap.inner.get_slider_velocity_at_time(start_time);
+        let slider_multiplier = self.beatmap.inner.difficulty.slider_multiplier;
+        let pixels_per_beat = slider_multiplier * 100.0 * slider_velocity;
+        let pixels_per_tick = pixels_per_beat / self.beat_divisor() as f64;
+
+        // a length that's already snapped can come out a hair short after scaling
+        let ticks = (len / pixels_per_tick + 1e-6).floor().max(1.0);
+        ticks * pixels_per_tick
+    }
+
+    /// The slider with new anchors and spline kind, with its length snapped to fit.
+    fn reshaped_slider(
+        &self,
+        ho: &HitObject,
+        kind: SliderSplineKind,
+        mut anchors: Vec<Point<i32>>,
+    ) -> HitObject {
+        let kind = fix_slider_kind(kind, anchors.len());
+        let pixel_length = self.snapped_slider_length(ho.start_time, kind, &anchors);
+
+        let mut ho = ho.clone();
+        ho.pos = anchors.remove(0);
+        if let HitObjectKind::Slider(info) = &mut ho.kind {
+            info.kind = kind;
+            info.control_points = anchors;
+            info.pixel_length = pixel_length;
+        }
+        ho
+    }
+
+    fn reshape_slider(
+        &mut self,
+        name: &str,
+        idx: usize,
+        kind: SliderSplineKind,
+        anchors: Vec<Point<i32>>,
+    ) {
+        let before = self.beatmap.hit_objects[idx].inner.clone();
+        let after = self.reshaped_slider(&before, kind, anchors);
+        let mut command = Command::new(name);
+        command.push(Change::ModifyHitObject {
+            index: idx,
+            before,
+            after,
+        });
+        self.execute(command);
+    }
+
+    fn edited_slider_kind(&self, idx: usize) -> SliderSplineKind {
+        match &self.beatmap.hit_objects[idx].inner.kind {
+            HitObjectKind::Slider(info) => info.kind,
+            _ => SliderSplineKind::Bezier,
+        }
+    }
+
+    /// Starts dragging an anchor if the mouse was pressed on one. Returns whether it was.
+    pub(super) fn begin_anchor_drag(&mut self, x: f32, y: f32) -> bool {
+        match self.anchor_at(x, y) {
+            Some((object, anchor)) => {
+                self.anchor_drag = Some(AnchorDrag { object, anchor });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Where the anchors of the slider would be if the drag ended right now.
+    fn dragged_anchors(&self) -> Option<(usize, Vec<Point<i32>>)> {
+        let drag = self.anchor_drag?;
+        let mut anchors = slider_anchors(&self.beatmap.hit_objects.get(drag.object)?.inner)?;
+        let (mx, my) = self.mouse_pos;
+        if Some((mx, my)) == self.left_drag_start {
+            return None;
+        }
+        let target = screen_to_osu(mx, my);
+        let grid_size = self.beatmap.inner.grid_size as i32;
+        let target = Point::new(
+            snap_to(target.x as f32, grid_size).max(0).min(512),
+            snap_to(target.y as f32, grid_size).max(0).min(384),
+        );
+
+        // red anchors are two anchors in the same place, so they move together
+        let original = anchors[drag.anchor];
+        let mut first = drag.anchor;
+        while first > 0 && anchors[first - 1] == original {
+            first -= 1;
+        }
+        for anchor in anchors[first..].iter_mut() {
+            if *anchor != original {
+                break;
+            }
+            *anchor = target;
+        }
+        Some((drag.object, anchors))
+    }
+
+    pub(super) fn finish_anchor_drag(&mut self) {
+        if let Some((idx, anchors)) = self.dragged_anchors() {
+            let kind = self.edited_slider_kind(idx);
+            self.reshape_slider("Move anchor", idx, kind, anchors);
+        }
+        self.anchor_drag = None;
+    }
+
+    /// Adds an anchor to the edited slider if the given screen position is on its body. It goes
+    /// between the two anchors whose segment of the control polygon is closest. Returns whether
+    /// one was added.
+    pub(super) fn insert_anchor_at(&mut self, x: f32, y: f32) -> bool {
+        let idx = match self.edited_slider() {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let ho = &self.beatmap.hit_objects[idx].inner;
+        let (info, mut anchors) = match (&ho.kind, slider_anchors(ho)) {
+            (HitObjectKind::Slider(info), Some(anchors)) => (info, anchors),
+            _ => return false,
+        };
+
+        // the body is drawn around the curve itself, which can be far from the control polygon
+        let spline = Spline::from_control(info.kind, &anchors, Some(info.pixel_length));
+        let cs_real =
+            self.beatmap.inner.difficulty.circle_size_osupx() * PLAYFIELD_BOUNDS.w / 640.0;
+        let on_body = spline.spline_points.windows(2).any(|pair| {
+            let a = osu_to_screen(pair[0].x as f32, pair[0].y as f32);
+            let b = osu_to_screen(pair[1].x as f32, pair[1].y as f32);
+            distance_to_segment([x, y], a, b) <= cs_real
+        });
+        if !on_body {
+            return false;
+        }
+
+        let closest = anchors
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let a = osu_to_screen(pair[0].x as f32, pair[0].y as f32);
+                let b = osu_to_screen(pair[1].x as f32, pair[1].y as f32);
+                (i, distance_to_segment([x, y], a, b))
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        match closest {
+            Some((segment, _)) => {
+                anchors.insert(segment + 1, screen_to_osu(x, y));
+                let kind = self.edited_slider_kind(idx);
+                self.reshape_slider("Add anchor", idx, kind, anchors);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the anchor of the edited slider under the given screen position. Sliders always
+    /// keep at least two anchors. Returns whether the position was on an anchor.
+    pub(super) fn delete_anchor_at(&mut self, x: f32, y: f32) -> bool {
+        let (idx, anchor) = match self.anchor_at(x, y) {
+            Some(found) => found,
+            None => return false,
+        };
+        let mut anchors = slider_anchors(&self.beatmap.hit_objects[idx].inner).unwrap();
+        if anchors.len() > 2 {
+            anchors.remove(anchor);
+            let kind = self.edited_slider_kind(idx);
+            self.reshape_slider("Delete anchor", idx, kind, anchors);
+        }
+        true
+    }
+
+    /// Turns the anchor under the given screen position into a red anchor (where the curve
+    /// breaks into a new segment) or back. Returns whether the position was on an anchor.
+    pub(super) fn toggle_red_anchor_at(&mut self, x: f32, y: f32) -> bool {
+        let (idx, anchor) = match self.anchor_at(x, y) {
+            Some(found) => found,
+            None => return false,
+        };
+        let mut anchors = slider_anchors(&self.beatmap.hit_objects[idx].inner).unwrap();
+
+        // the ends of the slider can't be red
+        if anchor == 0 || anchor == anchors.len() - 1 {
+            return true;
+        }
+        if anchors[anchor - 1] == anchors[anchor] {
+            anchors.remove(anchor);
+        } else if anchors[anchor + 1] == anchors[anchor] {
+            anchors.remove(anchor + 1);
+        } else {
+            anchors.insert(anchor, anchors[anchor]);
+        }
+
+        // red anchors only mean something for bezier curves
+        self.reshape_slider("Toggle red anchor", idx, SliderSplineKind::Bezier, anchors);
+        true
+    }
+
+    /// Changes the spline kind of the selected sliders, and snaps their lengths to the new shape.
+    pub(super) fn set_slider_kind(&mut self, kind: SliderSplineKind) {
+        let mut command = Command::new("Change slider type");
+        for idx in self.selected_objects.iter() {
+            let before = &self.beatmap.hit_objects[*idx].inner;
+            if let Some(anchors) = slider_anchors(before) {
+                command.push(Change::ModifyHitObject {
+                    index: *idx,
+                    before: before.clone(),
+                    after: self.reshaped_slider(before, kind, anchors),
+                });
+            }
+        }
+        self.execute(command);
+    }
+
+    /// The spline kind shared by all of the selected sliders.
+    pub(super) fn selection_slider_kind(&self) -> Option<SliderSplineKind> {
+        let mut kinds = self.selected_objects.iter().filter_map(|idx| {
+            match &self.beatmap.hit_objects[*idx].inner.kind {
+                HitObjectKind::Slider(info) => Some(info.kind),
+                _ => None,
+            }
+        });
+        let first = kinds.next()?;
+        if kinds.all(|kind| kind == first) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    /// Draws the slider being reshaped while one of its anchors is dragged.
+    pub(super) fn draw_anchor_drag(&self, ctx: &mut Context) -> Result<()> {
+        let (idx, anchors) = match self.dragged_anchors() {
+            Some(dragged) => dragged,
+            None => return Ok(()),
+        };
+
+        let ho = &self.beatmap.hit_objects[idx].inner;
+        let kind = fix_slider_kind(self.edited_slider_kind(idx), anchors.len());
+        let length = self.snapped_slider_length(ho.start_time, kind, &anchors);
+        let spline = Spline::from_control(kind, &anchors, Some(length));
+
+        let color = Color::new(1.0, 1.0, 1.0, 0.4);
+        let (track_color, border_color) = self.slider_colors(color);
+        Game::render_spline(
+            ctx,
+            &self.beatmap.inner,
+            &spline,
+            PLAYFIELD_BOUNDS,
+            track_color,
+            border_color,
+        )?;
+        Game::render_slider_wireframe(ctx, &anchors, PLAYFIELD_BOUNDS, Color::WHITE)?;
+        Ok(())
+    }
+}
@@ -9,7 +9,7 @@ use ggez::{
 };
 use libosu::{beatmap::Beatmap, hitobject::SliderInfo, math::Point, spline::Spline};
 
-use super::{Game, SliderCache};
+use super::{Game, SliderCache, SliderCacheKey};
 
 impl Game {
     pub fn render_spline(
@@ -18,6 +18,7 @@ impl Game {
         spline: &Spline,
         rect: Rect,
         color: Color,
+        border_color: Color,
     ) -> Result<()> {
         let cs_scale = rect.w / 640.0;
         let osupx_scale_x = rect.w as f64 / 512.0;
@@ -56,8 +57,6 @@ impl Game {
         graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
         graphics::draw(ctx, &body, DrawParam::default())?;
         graphics::set_canvas(ctx, None);
-        let mut border_color = Color::WHITE;
-        border_color.a = color.a;
         graphics::draw(ctx, &canvas, DrawParam::default().color(border_color))?;
 
         // draw slider body
@@ -89,6 +88,7 @@ impl Game {
         rect: Rect,
         beatmap: &Beatmap,
         color: Color,
+        border_color: Color,
     ) -> Result<()> {
         debug!(
             "Rendering slider body with control points {:?}",
@@ -102,17 +102,14 @@ impl Game {
             return Ok(());
         }
 
-        let spline = if slider_cache.contains_key(control_points) {
-            slider_cache.get(control_points).expect("just checked")
-        } else {
-            let new_spline = Spline::from_control(
+        let key = SliderCacheKey::new(slider_info, control_points);
+        let spline = slider_cache.entry(key).or_insert_with(|| {
+            Spline::from_control(
                 slider_info.kind,
                 control_points,
                 Some(slider_info.pixel_length),
-            );
-            slider_cache.insert(control_points.to_vec(), new_spline);
-            slider_cache.get(control_points).expect("just inserted it")
-        };
+            )
+        });
         debug!("spline length: {}", spline.spline_points.len());
 
         if spline.spline_points.len() < 2
@@ -123,7 +120,7 @@ impl Game {
             return Ok(());
         }
 
-        Game::render_spline(ctx, beatmap, spline, rect, color)
+        Game::render_spline(ctx, beatmap, spline, rect, color, border_color)
     }
 
     pub fn render_slider_wireframe(
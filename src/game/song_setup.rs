@@ -0,0 +1,232 @@
+use imgui::{ColorEdit, Slider, TabBar, TabItem, Ui, Window};
+
+use crate::beatmap::{BeatmapProperties, MODES};
+use crate::history::{Change, Command};
+
+use super::{ui::UiAction, Game, DEFAULT_COLORS};
+
+/// osu! only uses the first 8 combo colors.
+const MAX_COMBO_COLORS: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct SongSetupState {
+    pub opened: bool,
+    /// The values being edited. These are copied from the beatmap when the window is opened, and
+    /// only written back when OK is pressed.
+    properties: Option<BeatmapProperties>,
+    /// The colors that were last sent to the playfield to preview.
+    previewed: Option<ColorPreview>,
+}
+
+/// The colors from the Colors tab, shown on the playfield while they're being edited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorPreview {
+    pub combo_colors: Vec<[f32; 3]>,
+    pub slider_border: Option<[f32; 3]>,
+    pub slider_track_override: Option<[f32; 3]>,
+}
+
+impl ColorPreview {
+    fn new(properties: &BeatmapProperties) -> Self {
+        ColorPreview {
+            combo_colors: properties.combo_colors.clone(),
+            slider_border: properties.slider_border,
+            slider_track_override: properties.slider_track_override,
+        }
+    }
+}
+
+pub(super) fn draw_song_setup(ui: &Ui, state: &mut SongSetupState, actions: &mut Vec<UiAction>) {
+    let properties = match &mut state.properties {
+        Some(properties) => properties,
+        None => {
+            // the window was closed from the menu
+            if state.previewed.take().is_some() {
+                actions.push(UiAction::PreviewColors(None));
+            }
+            return;
+        }
+    };
+
+    let mut opened = true;
+    let mut closed = false;
+    Window::new("Song Setup")
+        .opened(&mut opened)
+        .collapsible(false)
+        .always_auto_resize(true)
+        .build(ui, || {
+            TabBar::new("song_setup").build(ui, || {
+                TabItem::new("General").build(ui, || {
+                    ui.input_text("Artist", &mut properties.artist).build();
+                    ui.input_text("Artist (Unicode)", &mut properties.artist_unicode)
+                        .build();
+                    ui.input_text("Title", &mut properties.title).build();
+                    ui.input_text("Title (Unicode)", &mut properties.title_unicode)
+                        .build();
+                    ui.input_text("Mapper", &mut properties.creator).build();
+                    ui.input_text("Difficulty", &mut properties.difficulty_name)
+                        .build();
+                    ui.input_text("Source", &mut properties.source).build();
+                    ui.input_text("Tags", &mut properties.tags).build();
+                });
+                TabItem::new("Difficulty").build(ui, || {
+                    Slider::new("HP Drain Rate", 0.0, 10.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.hp_drain_rate);
+                    Slider::new("Circle Size", 0.0, 10.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.circle_size);
+                    Slider::new("Approach Rate", 0.0, 10.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.approach_rate);
+                    Slider::new("Overall Difficulty", 0.0, 10.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.overall_difficulty);
+                    ui.separator();
+                    Slider::new("Slider Multiplier", 0.4, 3.6)
+                        .display_format("%.2f")
+                        .build(ui, &mut properties.slider_multiplier);
+                    Slider::new("Slider Tick Rate", 0.5, 8.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.slider_tick_rate);
+                });
+                TabItem::new("Audio").build(ui, || {
+                    ui.input_int("Audio Lead-in (ms)", &mut properties.audio_leadin)
+                        .build();
+                    ui.input_int("Preview Time (ms)", &mut properties.preview_time)
+                        .build();
+                });
+                TabItem::new("Colors").build(ui, || draw_colors_tab(ui, properties));
+                TabItem::new("Design").build(ui, || {});
+                TabItem::new("Advanced").build(ui, || {
+                    Slider::new("Stack Leniency", 0.2, 1.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut properties.stack_leniency);
+                    let mode_names = MODES.iter().map(|(_, name)| *name).collect::<Vec<_>>();
+                    ui.combo_simple_string("Mode", &mut properties.mode, &mode_names);
+                });
+            });
+
+            if ui.button("OK") {
+                actions.push(UiAction::ApplySongSetup(Box::new(properties.clone())));
+                closed = true;
+            }
+            ui.same_line();
+            if ui.button("Cancel") {
+                closed = true;
+            }
+        });
+
+    if !opened || closed {
+        state.opened = false;
+        state.properties = None;
+        state.previewed = None;
+        actions.push(UiAction::PreviewColors(None));
+        return;
+    }
+
+    let preview = ColorPreview::new(properties);
+    if state.previewed.as_ref() != Some(&preview) {
+        state.previewed = Some(preview.clone());
+        actions.push(UiAction::PreviewColors(Some(preview)));
+    }
+}
+
+fn draw_colors_tab(ui: &Ui, properties: &mut BeatmapProperties) {
+    let colors = &mut properties.combo_colors;
+    if colors.is_empty() {
+        ui.text_disabled("Using the default combo colors.");
+    }
+
+    let count = colors.len();
+    let mut swap = None;
+    let mut remove = None;
+    for (idx, color) in colors.iter_mut().enumerate() {
+        ColorEdit::new(&format!("Combo {}", idx + 1), color).build(ui);
+        ui.same_line();
+        if ui.small_button(&format!("Up##combo{}", idx)) && idx > 0 {
+            swap = Some((idx - 1, idx));
+        }
+        ui.same_line();
+        if ui.small_button(&format!("Down##combo{}", idx)) && idx + 1 < count {
+            swap = Some((idx, idx + 1));
+        }
+        ui.same_line();
+        if ui.small_button(&format!("Remove##combo{}", idx)) {
+            remove = Some(idx);
+        }
+    }
+    if let Some((a, b)) = swap {
+        colors.swap(a, b);
+    }
+    if let Some(idx) = remove {
+        colors.remove(idx);
+    }
+
+    if count < MAX_COMBO_COLORS && ui.button("Add Combo Color") {
+        // start from the colors that were being used, so adding one doesn't change the others
+        if colors.is_empty() {
+            colors.extend(DEFAULT_COLORS.iter().map(|(r, g, b)| [*r, *g, *b]));
+        } else {
+            colors.push([1.0, 1.0, 1.0]);
+        }
+    }
+
+    ui.separator();
+    optional_color(
+        ui,
+        "Slider Border",
+        &mut properties.slider_border,
+        [1.0, 1.0, 1.0],
+    );
+    optional_color(
+        ui,
+        "Slider Track Override",
+        &mut properties.slider_track_override,
+        [0.0, 0.0, 0.0],
+    );
+}
+
+/// A checkbox for whether a color is set at all, followed by a picker for it if it is.
+fn optional_color(ui: &Ui, label: &str, color: &mut Option<[f32; 3]>, default: [f32; 3]) {
+    let mut enabled = color.is_some();
+    if ui.checkbox(&format!("##{}_enabled", label), &mut enabled) {
+        *color = if enabled { Some(default) } else { None };
+    }
+    ui.same_line();
+    match color {
+        Some(color) => {
+            ColorEdit::new(label, color).build(ui);
+        }
+        None => ui.text_disabled(label),
+    }
+}
+
+impl Game {
+    /// Fills in the Song Setup window from the beatmap when it's opened.
+    pub(super) fn prepare_song_setup(&self, state: &mut SongSetupState) {
+        if !state.opened {
+            state.properties = None;
+        } else if state.properties.is_none() {
+            state.properties = Some(BeatmapProperties::from_beatmap(&self.beatmap));
+        }
+    }
+
+    /// Shows the given colors on the playfield instead of the beatmap's, or goes back to the
+    /// beatmap's colors if it's None.
+    pub(super) fn set_color_preview(&mut self, preview: Option<ColorPreview>) {
+        self.color_preview = preview;
+        self.update_combo_colors();
+        self.beatmap.compute_colors(&self.combo_colors);
+    }
+
+    pub(super) fn apply_song_setup(&mut self, properties: BeatmapProperties) {
+        let before = BeatmapProperties::from_beatmap(&self.beatmap);
+        let mut command = Command::new("Song setup");
+        command.push(Change::Properties {
+            before: Box::new(before),
+            after: Box::new(properties),
+        });
+        self.execute(command);
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::{Arc, Mutex};
+
+use imgui::{Condition, Ui, Window};
+use libosu::timing::{TimingPoint, TimingPointKind};
+
+use crate::audio::{FrequencyScale, Spectrogram, BANDS, FFT_SIZES};
+
+use super::timeline::BOUNDS as TIMELINE_BOUNDS;
+use super::{ui::UiAction, Game};
+
+/// Height of the spectrogram in its window. It's as wide as the timeline, so they share the same
+/// times.
+const HEIGHT: f32 = 128.0;
+
+/// Width of each column of the spectrogram, in screen pixels.
+const COLUMN_WIDTH: f32 = 4.0;
+
+const SCALES: &[(FrequencyScale, &str)] = &[
+    (FrequencyScale::Linear, "Linear"),
+    (FrequencyScale::Log, "Logarithmic"),
+    (FrequencyScale::Mel, "Mel"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpectrogramSettings {
+    pub fft_size: usize,
+    pub scale: FrequencyScale,
+}
+
+#[derive(Debug)]
+pub struct SpectrogramState {
+    pub opened: bool,
+    /// Index into `FFT_SIZES`.
+    fft_size: usize,
+    /// Index into `SCALES`.
+    scale: usize,
+}
+
+impl Default for SpectrogramState {
+    fn default() -> Self {
+        SpectrogramState {
+            opened: false,
+            fft_size: 3,
+            scale: 1,
+        }
+    }
+}
+
+/// An analysis of the song that was started with some settings.
+pub struct SpectrogramAnalysis {
+    settings: SpectrogramSettings,
+    result: Arc<Mutex<Option<Result<Arc<Spectrogram>, String>>>>,
+}
+
+/// What the spectrogram looks like around the current time, for the UI.
+#[derive(Debug)]
+pub enum SpectrogramView {
+    Off,
+    Analyzing,
+    Ready {
+        /// The loudness of each band in each column, or None where there's no song.
+        columns: Vec<Option<[f32; BANDS]>>,
+        /// Where each beat is from the left edge, and whether it starts a measure.
+        beats: Vec<(f32, bool)>,
+    },
+    Failed,
+}
+
+pub(super) fn draw_spectrogram_panel(
+    ui: &Ui,
+    state: &mut SpectrogramState,
+    view: &SpectrogramView,
+    actions: &mut Vec<UiAction>,
+) {
+    // there's no point in trying again with the same settings
+    if let SpectrogramView::Failed = view {
+        state.opened = false;
+    }
+    if !state.opened {
+        actions.push(UiAction::SetSpectrogram(None));
+        return;
+    }
+
+    let mut opened = true;
+    Window::new("Spectrogram")
+        .opened(&mut opened)
+        .always_auto_resize(true)
+        .position(
+            [0.0, TIMELINE_BOUNDS.y + TIMELINE_BOUNDS.h],
+            Condition::FirstUseEver,
+        )
+        .build(ui, || {
+            let sizes = FFT_SIZES
+                .iter()
+                .map(|(size, _)| size.to_string())
+                .collect::<Vec<_>>();
+            ui.set_next_item_width(120.0);
+            ui.combo_simple_string("FFT Size", &mut state.fft_size, &sizes);
+            ui.same_line();
+            let scales = SCALES.iter().map(|(_, name)| *name).collect::<Vec<_>>();
+            ui.set_next_item_width(120.0);
+            ui.combo_simple_string("Frequency Scale", &mut state.scale, &scales);
+
+            let origin = ui.cursor_screen_pos();
+            let width = TIMELINE_BOUNDS.w;
+            match view {
+                SpectrogramView::Ready { columns, beats } => {
+                    draw_spectrogram(ui, origin, columns, beats)
+                }
+                SpectrogramView::Analyzing => ui.text_disabled("Analyzing the song..."),
+                _ => ui.text_disabled("Open a beatmap to see its spectrogram."),
+            }
+            ui.set_cursor_screen_pos(origin);
+            ui.dummy([width, HEIGHT]);
+        });
+    state.opened = opened;
+
+    let settings = if state.opened {
+        Some(SpectrogramSettings {
+            fft_size: FFT_SIZES[state.fft_size].0,
+            scale: SCALES[state.scale].0,
+        })
+    } else {
+        None
+    };
+    actions.push(UiAction::SetSpectrogram(settings));
+}
+
+/// Draws the columns from the lowest band up, then lines on each beat and where the current
+/// time is.
+fn draw_spectrogram(
+    ui: &Ui,
+    origin: [f32; 2],
+    columns: &[Option<[f32; BANDS]>],
+    beats: &[(f32, bool)],
+) {
+    let draw_list = ui.get_window_draw_list();
+    let width = TIMELINE_BOUNDS.w;
+    let bottom = origin[1] + HEIGHT;
+    let band_height = HEIGHT / BANDS as f32;
+
+    draw_list
+        .add_rect(origin, [origin[0] + width, bottom], heat(0.0))
+        .filled(true)
+        .build();
+    for (column, bands) in columns.iter().enumerate() {
+        let bands = match bands {
+            Some(bands) => bands,
+            None => continue,
+        };
+        let x = origin[0] + column as f32 * COLUMN_WIDTH;
+        let right = (x + COLUMN_WIDTH).min(origin[0] + width);
+        for (band, value) in bands.iter().enumerate() {
+            let y = bottom - (band + 1) as f32 * band_height;
+            draw_list
+                .add_rect([x, y], [right, y + band_height], heat(*value))
+                .filled(true)
+                .build();
+        }
+    }
+
+    for (x, downbeat) in beats {
+        let alpha = if *downbeat { 0.7 } else { 0.3 };
+        draw_list
+            .add_line(
+                [origin[0] + x, origin[1]],
+                [origin[0] + x, bottom],
+                [1.0, 1.0, 1.0, alpha],
+            )
+            .build();
+    }
+    let center = origin[0] + width / 2.0;
+    draw_list
+        .add_line([center, origin[1]], [center, bottom], [1.0, 1.0, 1.0, 1.0])
+        .thickness(2.0)
+        .build();
+}
+
+/// Maps a loudness from 0 to 1 onto a color going from black through purple and orange to
+/// yellow.
+fn heat(value: f32) -> [f32; 4] {
+    const STOPS: &[[f32; 3]] = &[
+        [0.0, 0.0, 0.0],
+        [0.35, 0.0, 0.6],
+        [1.0, 0.35, 0.0],
+        [1.0, 1.0, 0.6],
+    ];
+    let scaled = value.max(0.0).min(1.0) * (STOPS.len() - 1) as f32;
+    let idx = (scaled.floor() as usize).min(STOPS.len() - 2);
+    let t = scaled - idx as f32;
+    let (a, b) = (STOPS[idx], STOPS[idx + 1]);
+    let mix = |i: usize| a[i] + (b[i] - a[i]) * t;
+    [mix(0), mix(1), mix(2), 1.0]
+}
+
+impl Game {
+    /// Starts analyzing the song again whenever the settings change.
+    pub(super) fn set_spectrogram_settings(&mut self, settings: Option<SpectrogramSettings>) {
+        let current = self.spectrogram.as_ref().map(|analysis| analysis.settings);
+        if current == settings {
+            return;
+        }
+
+        self.spectrogram = match (&self.song, settings) {
+            (Some(song), Some(settings)) => Some(SpectrogramAnalysis {
+                settings,
+                result: song.analyze_spectrogram(settings.fft_size, settings.scale),
+            }),
+            _ => None,
+        };
+    }
+
+    /// The part of the spectrogram that lines up with the timeline at the given time (in
+    /// seconds). If the song couldn't be analyzed, the spectrogram is turned off.
+    pub(super) fn spectrogram_view(&mut self, time: f64) -> SpectrogramView {
+        let analysis = match &self.spectrogram {
+            Some(analysis) => analysis,
+            None => return SpectrogramView::Off,
+        };
+        let result = analysis.result.lock().unwrap().clone();
+        let spectrogram = match result {
+            None => return SpectrogramView::Analyzing,
+            Some(Ok(spectrogram)) => spectrogram,
+            Some(Err(err)) => {
+                warn!("couldn't analyze the spectrogram of the song: {}", err);
+                self.spectrogram = None;
+                return SpectrogramView::Failed;
+            }
+        };
+
+        let num_columns = (TIMELINE_BOUNDS.w / COLUMN_WIDTH).ceil() as usize;
+        let columns = (0..num_columns)
+            .map(|column| {
+                let x = TIMELINE_BOUNDS.x + column as f32 * COLUMN_WIDTH;
+                let start = self.timeline_time_at(x, time);
+                let end = self.timeline_time_at(x + COLUMN_WIDTH, time);
+                spectrogram.bands_between(start, end)
+            })
+            .collect();
+
+        SpectrogramView::Ready {
+            columns,
+            beats: self.spectrogram_beats(time),
+        }
+    }
+
+    /// Where each beat of the current timing section is on the timeline, and whether it starts a
+    /// measure.
+    fn spectrogram_beats(&self, time: f64) -> Vec<(f32, bool)> {
+        let (offset, info) = match &self.current_uninherited_timing_point {
+            Some(TimingPoint {
+                kind: TimingPointKind::Uninherited(info),
+                time,
+                ..
+            }) if info.mpb > 0.0 => (time.as_seconds(), info),
+            _ => return Vec::new(),
+        };
+
+        let beat = info.mpb / 1000.0;
+        let meter = info.meter.max(1) as i64;
+        let left = self.timeline_time_at(TIMELINE_BOUNDS.x, time);
+        let right = self.timeline_time_at(TIMELINE_BOUNDS.x + TIMELINE_BOUNDS.w, time);
+
+        let mut beats = Vec::new();
+        let mut beat_idx = ((left - offset) / beat).ceil() as i64;
+        while offset + beat_idx as f64 * beat <= right {
+            let x = self.timeline_x_at(offset + beat_idx as f64 * beat, time) - TIMELINE_BOUNDS.x;
+            beats.push((x, beat_idx.rem_euclid(meter) == 0));
+            beat_idx += 1;
+        }
+        beats
+    }
+}
@@ -1,6 +1,9 @@
 use anyhow::Result;
 use ggez::{
-    graphics::{self, Color, DrawMode, DrawParam, LineCap, Mesh, Rect, StrokeOptions},
+    graphics::{
+        self, Color, DrawMode, DrawParam, FillOptions, LineCap, Mesh, MeshBuilder, Rect,
+        StrokeOptions,
+    },
     mint::Point2,
     Context,
 };
@@ -8,19 +11,50 @@ use libosu::{hitobject::HitObjectKind, timing::TimingPointKind};
 
 use crate::hitobject::HitObjectExt;
 
-use super::Game;
+use super::hitsound_edit::{draw_addition_markers, edge_additions};
+use super::{Game, BEAT_DIVISORS};
 
 pub const BOUNDS: Rect = Rect::new(0.0, 54.0, 768.0, 54.0);
 
+/// How far the timeline can be zoomed out and in. At 1x it shows 6 seconds.
+pub const TIMELINE_ZOOM_RANGE: (f64, f64) = (0.25, 8.0);
+
 pub const RED: Color = Color::new(1.0, 0.0, 0.0, 1.0);
 pub const BLUE: Color = Color::new(0.0, 0.0, 1.0, 1.0);
-pub const TICKS: &[&[(Color, f32)]] = &[
-    &[],
-    &[],
-    &[],
-    &[],
-    &[(Color::WHITE, 1.0), (BLUE, 0.5), (RED, 0.5), (BLUE, 0.5)],
-];
+pub const PURPLE: Color = Color::new(0.7, 0.0, 1.0, 1.0);
+pub const YELLOW: Color = Color::new(1.0, 0.9, 0.0, 1.0);
+
+/// Color and relative height of the tick `i` out of a beat that's divided into `divisor` ticks.
+///
+/// Like in osu!, ticks are colored by the smallest divisor that would also have a tick there.
+pub fn tick_style(i: u32, divisor: u32) -> (Color, f32) {
+    let smallest = BEAT_DIVISORS
+        .iter()
+        .find(|d| (i * *d) % divisor == 0)
+        .cloned()
+        .unwrap_or(divisor);
+
+    match smallest {
+        1 => (Color::WHITE, 0.8),
+        2 => (RED, 0.6),
+        4 => (BLUE, 0.5),
+        3 | 6 | 12 => (PURPLE, 0.5),
+        _ => (YELLOW, 0.4),
+    }
+}
+
+/// The beat divisor that's `steps` further along `BEAT_DIVISORS` from `divisor`, stopping at
+/// either end.
+pub fn step_divisor(divisor: u32, steps: i32) -> u32 {
+    let current = match BEAT_DIVISORS.iter().position(|d| *d >= divisor) {
+        // a divisor that isn't in the list (from the .osu file) sits between two that are
+        Some(idx) if BEAT_DIVISORS[idx] != divisor && steps > 0 => idx as i32 - 1,
+        Some(idx) => idx as i32,
+        None => BEAT_DIVISORS.len() as i32,
+    };
+    let idx = (current + steps).max(0).min(BEAT_DIVISORS.len() as i32 - 1);
+    BEAT_DIVISORS[idx as usize]
+}
 
 impl Game {
     pub(super) fn draw_timeline(&self, ctx: &mut Context, time: f64) -> Result<()> {
@@ -81,13 +115,20 @@ impl Game {
                         && ((snd_time.is_some() && snd_time.unwrap() > timeline_right)
                             || snd_time.is_none()))
                 {
-                    // TODO: optimize this
                     let beat = last_uninherited.mpb / 1000.0;
-                    let ticks = TICKS[last_uninherited.meter as usize];
+                    if beat <= 0.0 {
+                        continue;
+                    }
+                    let meter = last_uninherited.meter.max(1) as i64;
+                    let divisor = self.beat_divisor();
 
-                    let mut time = fst.time.as_seconds();
-                    let passed_measures = ((timeline_left - time) / beat).floor();
-                    time += passed_measures * beat;
+                    // the first timing point also gets ticks before it, the others start at their
+                    // own time since the previous section runs until then
+                    let section_start = fst.time.as_seconds();
+                    let mut beat_idx = ((timeline_left - section_start) / beat).floor() as i64;
+                    if i > 0 {
+                        beat_idx = beat_idx.max(0);
+                    }
 
                     let mut right_limit = timeline_right;
                     if let Some(snd_time) = snd_time {
@@ -95,15 +136,21 @@ impl Game {
                     }
 
                     'outer: loop {
-                        for (i, (color, height)) in ticks
-                            .iter()
-                            .enumerate()
-                            .take(last_uninherited.meter as usize)
-                        {
-                            let tick_time = time + beat * i as f64 / last_uninherited.meter as f64;
+                        let beat_time = section_start + beat_idx as f64 * beat;
+                        for tick_idx in 0..divisor {
+                            let tick_time = beat_time + beat * tick_idx as f64 / divisor as f64;
                             if tick_time > right_limit {
                                 break 'outer;
                             }
+                            if tick_time < timeline_left {
+                                continue;
+                            }
+
+                            let (color, mut height) = tick_style(tick_idx, divisor);
+                            if tick_idx == 0 && beat_idx.rem_euclid(meter) == 0 {
+                                // start of a measure
+                                height = 1.0;
+                            }
 
                             let percent =
                                 (tick_time - timeline_left) / (timeline_right - timeline_left);
@@ -114,15 +161,11 @@ impl Game {
                                 ctx,
                                 &[Point2::from([x, y1]), Point2::from([x, y2])],
                                 1.0,
-                                *color,
+                                color,
                             )?;
                             graphics::draw(ctx, &tick, DrawParam::default())?;
                         }
-                        time += beat;
-
-                        if time >= right_limit {
-                            break;
-                        }
+                        beat_idx += 1;
                     }
                 }
             }
@@ -143,11 +186,109 @@ impl Game {
         Ok(())
     }
 
+    /// Draws the waveform of the song behind the timeline, once it's been decoded.
+    pub(super) fn draw_waveform(&self, ctx: &mut Context, time: f64) -> Result<()> {
+        let waveform = match self.song.as_ref().and_then(|song| song.waveform()) {
+            Some(waveform) => waveform,
+            None => return Ok(()),
+        };
+
+        // one bar every few pixels is plenty, and keeps the mesh small
+        let bar_width = 2.0;
+        let center = BOUNDS.y + BOUNDS.h / 2.0;
+        let scale = BOUNDS.h / 2.0;
+        let mut builder = MeshBuilder::new();
+        let mut empty = true;
+        let mut x = BOUNDS.x;
+        while x < BOUNDS.x + BOUNDS.w {
+            let start = self.timeline_time_at(x, time);
+            let end = self.timeline_time_at(x + bar_width, time);
+            if let Some(peak) = waveform.peak_between(start, end) {
+                let top = center - peak.max.min(1.0) * scale;
+                let bottom = center - peak.min.max(-1.0) * scale;
+                builder.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(x, top, bar_width, (bottom - top).max(1.0)),
+                    Color::new(0.3, 0.5, 0.7, 0.5),
+                )?;
+                let rms = peak.rms.min(1.0) * scale;
+                builder.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(x, center - rms, bar_width, (rms * 2.0).max(1.0)),
+                    Color::new(0.5, 0.75, 1.0, 0.6),
+                )?;
+                empty = false;
+            }
+            x += bar_width;
+        }
+
+        if !empty {
+            let mesh = builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    /// Zooms the timeline in (or out, for negative steps) around the current time. The zoom is
+    /// saved with the beatmap.
+    pub(super) fn zoom_timeline(&mut self, steps: f32) {
+        let (min, max) = TIMELINE_ZOOM_RANGE;
+        let zoom = self.beatmap.inner.timeline_zoom.max(min) * 1.1f64.powf(steps as f64);
+        self.beatmap.inner.timeline_zoom = zoom.max(min).min(max);
+    }
+
+    /// Converts an x coordinate on the timeline into a time (in seconds).
+    pub(super) fn timeline_time_at(&self, x: f32, time: f64) -> f64 {
+        let timeline_span = 6.0 / self.beatmap.inner.timeline_zoom;
+        let timeline_left = time - timeline_span / 2.0;
+        timeline_left + ((x - BOUNDS.x) / BOUNDS.w) as f64 * timeline_span
+    }
+
+    /// Converts a time (in seconds) into an x coordinate on the timeline.
+    pub(super) fn timeline_x_at(&self, at: f64, time: f64) -> f32 {
+        let timeline_span = 6.0 / self.beatmap.inner.timeline_zoom;
+        let timeline_left = time - timeline_span / 2.0;
+        ((at - timeline_left) / timeline_span) as f32 * BOUNDS.w + BOUNDS.x
+    }
+
+    /// Highlights the span of time between start and end (in seconds) on the timeline.
+    pub(super) fn draw_timeline_range(
+        &self,
+        ctx: &mut Context,
+        time: f64,
+        start: f64,
+        end: f64,
+        color: Color,
+    ) -> Result<()> {
+        let timeline_span = 6.0 / self.beatmap.inner.timeline_zoom;
+        let timeline_left = time - timeline_span / 2.0;
+        let timeline_right = time + timeline_span / 2.0;
+
+        let (start, end) = (start.min(end), start.max(end));
+        let start = start.max(timeline_left);
+        let end = end.min(timeline_right);
+        if start >= end {
+            return Ok(());
+        }
+
+        let start_x = ((start - timeline_left) / timeline_span) as f32 * BOUNDS.w + BOUNDS.x;
+        let end_x = ((end - timeline_left) / timeline_span) as f32 * BOUNDS.w + BOUNDS.x;
+        let rect = Mesh::new_rectangle(
+            ctx,
+            DrawMode::Fill(FillOptions::default()),
+            Rect::new(start_x, BOUNDS.y, end_x - start_x, BOUNDS.h),
+            color,
+        )?;
+        graphics::draw(ctx, &rect, DrawParam::default())?;
+        Ok(())
+    }
+
     pub(super) fn draw_hitobject_to_timeline(
         &self,
         ctx: &mut Context,
         time: f64,
         ho: &HitObjectExt,
+        selected: bool,
     ) -> Result<()> {
         let timeline_span = 6.0 / self.beatmap.inner.timeline_zoom;
         let timeline_left = time - timeline_span / 2.0;
@@ -249,6 +390,47 @@ impl Game {
                 }
             }
 
+            // spinners are just a long bar
+            if let HitObjectKind::Spinner(_) = &ho.inner.kind {
+                let body_y = BOUNDS.y + BOUNDS.h / 2.0;
+                let body = Mesh::new_polyline(
+                    ctx,
+                    DrawMode::Stroke(
+                        StrokeOptions::default()
+                            .with_line_width(BOUNDS.h)
+                            .with_line_cap(LineCap::Round),
+                    ),
+                    &[
+                        Point2::from([head_x, body_y]),
+                        Point2::from([tail_x, body_y]),
+                    ],
+                    Color::new(1.0, 1.0, 1.0, 0.5),
+                )?;
+                graphics::draw(ctx, &body, DrawParam::default())?;
+
+                if end_time < timeline_right {
+                    self.skin.hitcircleoverlay.draw(
+                        ctx,
+                        (BOUNDS.h, BOUNDS.h),
+                        DrawParam::default()
+                            .dest([tail_x, timeline_y + BOUNDS.h / 2.0])
+                            .offset([0.5, 0.0]),
+                    )?;
+                }
+                self.skin.hitcircleoverlay.draw(
+                    ctx,
+                    (BOUNDS.h, BOUNDS.h),
+                    DrawParam::default()
+                        .dest([head_x, timeline_y + BOUNDS.h / 2.0])
+                        .offset([0.5, 0.0]),
+                )?;
+                if selected {
+                    self.draw_timeline_selection(ctx, head_x)?;
+                }
+                self.draw_timeline_additions(ctx, time, ho)?;
+                return Ok(());
+            }
+
             // draw the slider head
             self.skin.hitcircle.draw(
                 ctx,
@@ -274,8 +456,105 @@ impl Game {
                 BOUNDS.h / 2.0,
                 Color::WHITE,
             )?;
+            if selected {
+                self.draw_timeline_selection(ctx, head_x)?;
+            }
+            self.draw_timeline_additions(ctx, time, ho)?;
         }
 
         Ok(())
     }
+
+    /// Outlines the head of a selected object on the timeline.
+    fn draw_timeline_selection(&self, ctx: &mut Context, head_x: f32) -> Result<()> {
+        self.skin.hitcircleselect.draw(
+            ctx,
+            (BOUNDS.h, BOUNDS.h),
+            DrawParam::default()
+                .dest([head_x, BOUNDS.y + BOUNDS.h / 2.0])
+                .offset([0.5, 0.0]),
+        )
+    }
+
+    /// Marks the additions under each edge of an object on the timeline.
+    fn draw_timeline_additions(
+        &self,
+        ctx: &mut Context,
+        time: f64,
+        ho: &HitObjectExt,
+    ) -> Result<()> {
+        let timeline_span = 6.0 / self.beatmap.inner.timeline_zoom;
+        let timeline_left = time - timeline_span / 2.0;
+        let timeline_right = time + timeline_span / 2.0;
+
+        let start_time = ho.inner.start_time.as_seconds();
+        let end_time = self
+            .beatmap
+            .inner
+            .get_hitobject_end_time(&ho.inner)
+            .unwrap();
+        let additions = edge_additions(&ho.inner);
+        for (edge, additions) in additions.iter().enumerate() {
+            // spinners only make a sound when they end
+            let edge_time = match &ho.inner.kind {
+                HitObjectKind::Slider(info) => {
+                    start_time + (end_time - start_time) * edge as f64 / info.num_repeats as f64
+                }
+                HitObjectKind::Spinner(_) => end_time,
+                HitObjectKind::Circle => start_time,
+            };
+            if edge_time < timeline_left || edge_time > timeline_right {
+                continue;
+            }
+
+            let x = ((edge_time - timeline_left) / timeline_span) as f32 * BOUNDS.w + BOUNDS.x;
+            let y = BOUNDS.y + BOUNDS.h - 4.0;
+            draw_addition_markers(ctx, *additions, [x, y], 3.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_use_the_smallest_divisor() {
+        assert_eq!(tick_style(0, 4), (Color::WHITE, 0.8));
+        assert_eq!(tick_style(1, 4), (BLUE, 0.5));
+        assert_eq!(tick_style(2, 4), (RED, 0.6));
+        assert_eq!(tick_style(3, 4), (BLUE, 0.5));
+
+        assert_eq!(tick_style(2, 6), (PURPLE, 0.5));
+        assert_eq!(tick_style(3, 6), (RED, 0.6));
+        assert_eq!(tick_style(3, 12), (BLUE, 0.5));
+        assert_eq!(tick_style(4, 12), (PURPLE, 0.5));
+        assert_eq!(tick_style(1, 16), (YELLOW, 0.4));
+        assert_eq!(tick_style(4, 16), (BLUE, 0.5));
+    }
+
+    #[test]
+    fn ticks_of_unlisted_divisors() {
+        assert_eq!(tick_style(0, 5), (Color::WHITE, 0.8));
+        assert_eq!(tick_style(1, 5), (YELLOW, 0.4));
+    }
+
+    #[test]
+    fn step_through_divisors() {
+        assert_eq!(step_divisor(4, 1), 6);
+        assert_eq!(step_divisor(4, -1), 3);
+        assert_eq!(step_divisor(4, 2), 8);
+        assert_eq!(step_divisor(2, -3), 1);
+        assert_eq!(step_divisor(1, -1), 1);
+        assert_eq!(step_divisor(16, 1), 16);
+    }
+
+    #[test]
+    fn step_from_unlisted_divisors() {
+        assert_eq!(step_divisor(5, 1), 6);
+        assert_eq!(step_divisor(5, -1), 4);
+        assert_eq!(step_divisor(32, -1), 16);
+        assert_eq!(step_divisor(32, 1), 16);
+    }
 }
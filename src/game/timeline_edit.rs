@@ -0,0 +1,349 @@
+use anyhow::Result;
+use ggez::{graphics::Color, Context};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    timing::Millis,
+};
+
+use crate::history::{Change, Command};
+use crate::utils::rect_contains;
+
+use super::timeline::BOUNDS;
+use super::{Game, Tool};
+
+/// How close (in screen pixels) the mouse has to be to the end of a spinner on the timeline to
+/// grab it.
+const END_GRAB_DISTANCE: f32 = 8.0;
+
+/// Something being dragged on the timeline with the select tool.
+#[derive(Clone, Copy, Debug)]
+pub enum TimelineDrag {
+    /// The selection is being moved in time, holding on to this object.
+    Move(usize),
+    /// The end of this spinner is being moved.
+    SpinnerEnd(usize),
+    /// A box is being dragged out to select everything in it.
+    Select,
+    /// The timeline is being dragged along to seek, starting from this time (in seconds).
+    Scrub(f64),
+}
+
+/// The object moved in time, along with its end if it's a spinner.
+fn retimed(ho: &HitObject, delta: i32) -> HitObject {
+    let mut ho = ho.clone();
+    ho.start_time = Millis(ho.start_time.0 + delta);
+    if let HitObjectKind::Spinner(info) = &mut ho.kind {
+        info.end_time = Millis(info.end_time.0 + delta);
+    }
+    ho
+}
+
+impl Game {
+    /// The object whose head is under the given x coordinate on the timeline. Earlier objects are
+    /// drawn on top, so they're picked first.
+    fn timeline_object_at(&self, x: f32, time: f64) -> Option<usize> {
+        self.beatmap.hit_objects.iter().position(|ho| {
+            let head_x = self.timeline_x_at(ho.inner.start_time.as_seconds(), time);
+            (head_x - x).abs() <= BOUNDS.h / 2.0
+        })
+    }
+
+    /// The spinner whose end is under the given x coordinate on the timeline, preferring ones
+    /// that are selected.
+    fn timeline_spinner_end_at(&self, x: f32, time: f64) -> Option<usize> {
+        let is_grabbed = |idx: usize| {
+            let ho = &self.beatmap.hit_objects[idx].inner;
+            match &ho.kind {
+                HitObjectKind::Spinner(info) => {
+                    let end_x = self.timeline_x_at(info.end_time.as_seconds(), time);
+                    (end_x - x).abs() <= END_GRAB_DISTANCE
+                }
+                _ => false,
+            }
+        };
+        self.selected_objects
+            .iter()
+            .cloned()
+            .find(|idx| is_grabbed(*idx))
+            .or_else(|| (0..self.beatmap.hit_objects.len()).find(|idx| is_grabbed(*idx)))
+    }
+
+    /// Starts a drag on the timeline: the end of a spinner, the objects under the mouse, or
+    /// otherwise the timeline itself to scrub through the song. Holding shift or control on empty
+    /// space drags out a selection box instead. Objects that are pressed on get selected right
+    /// away.
+    pub(super) fn begin_timeline_drag(&mut self, x: f32, y: f32, time: f64) {
+        if !rect_contains(&BOUNDS, x, y) {
+            return;
+        }
+
+        // the other tools can only scrub, except while the spinner tool is using the timeline to
+        // pick where a spinner ends
+        if !matches!(self.tool, Tool::Select) {
+            if self.partial_spinner_state.is_none() {
+                self.timeline_drag = Some(TimelineDrag::Scrub(time));
+            }
+            return;
+        }
+
+        let additive = self.is_additive_selection();
+        self.timeline_drag = if let Some(idx) = self.timeline_spinner_end_at(x, time) {
+            Some(TimelineDrag::SpinnerEnd(idx))
+        } else if let Some(idx) = self.timeline_object_at(x, time) {
+            if additive {
+                if let Some(pos) = self.selected_objects.iter().position(|i| *i == idx) {
+                    self.selected_objects.remove(pos);
+                } else {
+                    self.selected_objects.push(idx);
+                }
+                None
+            } else {
+                if !self.selected_objects.contains(&idx) {
+                    self.selected_objects = vec![idx];
+                }
+                Some(TimelineDrag::Move(idx))
+            }
+        } else if additive {
+            Some(TimelineDrag::Select)
+        } else {
+            Some(TimelineDrag::Scrub(time))
+        };
+    }
+
+    /// Seeks so the time that was under the mouse when the scrub started stays under the mouse.
+    pub(super) fn scrub_timeline(&mut self, x: f32) -> Result<()> {
+        let (start_time, (start_x, _)) = match (self.timeline_drag, self.left_drag_start) {
+            (Some(TimelineDrag::Scrub(start_time)), Some(start)) => (start_time, start),
+            _ => return Ok(()),
+        };
+
+        let offset =
+            self.timeline_time_at(x, start_time) - self.timeline_time_at(start_x, start_time);
+        let mut target = (start_time - offset).max(0.0);
+        if let Some(song) = &self.song {
+            target = target.min(song.length()?);
+        }
+        self.jump_to_time(target)
+    }
+
+    /// How far the mouse has moved along the timeline since the drag started, in seconds.
+    fn timeline_drag_distance(&self, time: f64) -> Option<f64> {
+        let (start_x, _) = self.left_drag_start?;
+        let (mx, _) = self.mouse_pos;
+        if start_x == mx {
+            return None;
+        }
+        Some(self.timeline_time_at(mx, time) - self.timeline_time_at(start_x, time))
+    }
+
+    /// How far (in milliseconds) the selection would be moved if the drag ended right now. The
+    /// grabbed object is snapped to the beat and the rest keep their distance from it.
+    fn dragged_time_offset(&self, time: f64) -> Option<i32> {
+        let anchor = match self.timeline_drag? {
+            TimelineDrag::Move(idx) => idx,
+            _ => return None,
+        };
+        let distance = self.timeline_drag_distance(time)?;
+        let start_time = self.beatmap.hit_objects.get(anchor)?.inner.start_time;
+        let new_time = self.snap_time(start_time.as_seconds() + distance);
+        match new_time.0 - start_time.0 {
+            0 => None,
+            delta => Some(delta),
+        }
+    }
+
+    /// Whether moving the selection by this much would put an object on the same time as one
+    /// that isn't being moved.
+    fn time_offset_collides(&self, delta: i32) -> bool {
+        self.selected_objects.iter().any(|idx| {
+            let new_time = Millis(self.beatmap.hit_objects[*idx].inner.start_time.0 + delta);
+            self.beatmap.hit_objects.iter().enumerate().any(|(i, ho)| {
+                ho.inner.start_time == new_time && !self.selected_objects.contains(&i)
+            })
+        })
+    }
+
+    /// Where the end of the spinner being dragged would be if the drag ended right now.
+    fn dragged_spinner_end(&self, time: f64) -> Option<(usize, Millis)> {
+        let idx = match self.timeline_drag? {
+            TimelineDrag::SpinnerEnd(idx) => idx,
+            _ => return None,
+        };
+        let ho = &self.beatmap.hit_objects.get(idx)?.inner;
+        self.timeline_drag_distance(time)?;
+
+        let (mx, _) = self.mouse_pos;
+        let end_time = self.snap_time(self.timeline_time_at(mx, time));
+        if end_time <= ho.start_time {
+            return None;
+        }
+        Some((idx, end_time))
+    }
+
+    pub(super) fn finish_timeline_drag(&mut self, time: f64) -> Result<()> {
+        match self.timeline_drag {
+            Some(TimelineDrag::Move(_)) => {
+                if let Some(delta) = self.dragged_time_offset(time) {
+                    self.move_selection_in_time(delta);
+                }
+            }
+            Some(TimelineDrag::SpinnerEnd(_)) => {
+                if let Some((idx, end_time)) = self.dragged_spinner_end(time) {
+                    let before = self.beatmap.hit_objects[idx].inner.clone();
+                    let mut after = before.clone();
+                    if let HitObjectKind::Spinner(info) = &mut after.kind {
+                        info.end_time = end_time;
+                    }
+                    let mut command = Command::new("Change spinner length");
+                    command.push(Change::ModifyHitObject {
+                        index: idx,
+                        before,
+                        after,
+                    });
+                    self.execute(command);
+                }
+            }
+            Some(TimelineDrag::Scrub(_)) => {
+                let (mx, _) = self.mouse_pos;
+                self.scrub_timeline(mx)?;
+                self.timestamp_changed()?;
+            }
+            Some(TimelineDrag::Select) => {
+                if let Some((start_x, _)) = self.left_drag_start {
+                    let (mx, _) = self.mouse_pos;
+                    let start = self.timeline_time_at(start_x.min(mx), time);
+                    let end = self.timeline_time_at(start_x.max(mx), time);
+                    self.select_in_time_range(start, end);
+                }
+            }
+            None => {}
+        }
+        self.timeline_drag = None;
+        Ok(())
+    }
+
+    /// Handles a single click on the timeline. Clicking on nothing seeks there.
+    pub(super) fn click_timeline(&mut self, x: f32, time: f64) -> Result<()> {
+        if matches!(
+            self.timeline_drag,
+            Some(TimelineDrag::Select) | Some(TimelineDrag::Scrub(_)) | None
+        ) && self.repeat_drag.is_none()
+        {
+            if self.timeline_object_at(x, time).is_none() {
+                let target = self.snap_time(self.timeline_time_at(x, time));
+                self.jump_to_time(target.as_seconds())?;
+            }
+        }
+        self.timeline_drag = None;
+        Ok(())
+    }
+
+    /// Selects every object that starts between the two times (in seconds).
+    fn select_in_time_range(&mut self, start: f64, end: f64) {
+        if !self.is_additive_selection() {
+            self.selected_objects.clear();
+        }
+
+        for (idx, ho) in self.beatmap.hit_objects.iter().enumerate() {
+            let ho_time = ho.inner.start_time.as_seconds();
+            if ho_time >= start && ho_time <= end && !self.selected_objects.contains(&idx) {
+                self.selected_objects.push(idx);
+            }
+        }
+    }
+
+    /// Moves the selected objects in time, as long as none of them end up on top of another
+    /// object.
+    fn move_selection_in_time(&mut self, delta: i32) {
+        if self.time_offset_collides(delta) {
+            warn!("not moving the selection since it would overlap other objects");
+            return;
+        }
+
+        let mut selected = self.selected_objects.clone();
+        selected.sort_unstable();
+        let moved = selected
+            .iter()
+            .map(|idx| retimed(&self.beatmap.hit_objects[*idx].inner, delta))
+            .collect::<Vec<_>>();
+
+        // take everything out, then put it back in where it belongs now
+        let mut command = Command::new("Move in time");
+        for idx in selected.iter().rev() {
+            command.push(Change::RemoveHitObject {
+                index: *idx,
+                object: self.beatmap.hit_objects[*idx].inner.clone(),
+            });
+        }
+        let remaining = (0..self.beatmap.hit_objects.len())
+            .filter(|idx| !selected.contains(idx))
+            .map(|idx| self.beatmap.hit_objects[idx].inner.start_time)
+            .collect::<Vec<_>>();
+        let mut indices = Vec::new();
+        for (i, ho) in moved.into_iter().enumerate() {
+            let index = i + remaining
+                .iter()
+                .take_while(|start_time| **start_time < ho.start_time)
+                .count();
+            indices.push(index);
+            command.push(Change::InsertHitObject { index, object: ho });
+        }
+        self.execute(command);
+        self.selected_objects = indices;
+    }
+
+    /// Shows what the drag on the timeline would do.
+    pub(super) fn draw_timeline_drag(&self, ctx: &mut Context, time: f64) -> Result<()> {
+        match self.timeline_drag {
+            Some(TimelineDrag::Move(_)) => {
+                let delta = match self.dragged_time_offset(time) {
+                    Some(delta) => delta,
+                    None => return Ok(()),
+                };
+                let color = if self.time_offset_collides(delta) {
+                    Color::new(1.0, 0.3, 0.3, 0.3)
+                } else {
+                    Color::new(1.0, 1.0, 1.0, 0.3)
+                };
+                for idx in self.selected_objects.iter() {
+                    let ho = retimed(&self.beatmap.hit_objects[*idx].inner, delta);
+                    let start_time = ho.start_time.as_seconds();
+                    let end_time = self
+                        .beatmap
+                        .inner
+                        .get_hitobject_end_time(&ho)
+                        .unwrap_or(start_time);
+                    // circles still get a sliver so they show up
+                    let end_time = end_time.max(start_time + 0.01);
+                    self.draw_timeline_range(ctx, time, start_time, end_time, color)?;
+                }
+            }
+            Some(TimelineDrag::SpinnerEnd(_)) => {
+                if let Some((idx, end_time)) = self.dragged_spinner_end(time) {
+                    let start_time = self.beatmap.hit_objects[idx].inner.start_time;
+                    self.draw_timeline_range(
+                        ctx,
+                        time,
+                        start_time.as_seconds(),
+                        end_time.as_seconds(),
+                        Color::new(1.0, 1.0, 1.0, 0.3),
+                    )?;
+                }
+            }
+            Some(TimelineDrag::Select) => {
+                if let Some((start_x, _)) = self.left_drag_start {
+                    let (mx, _) = self.mouse_pos;
+                    self.draw_timeline_range(
+                        ctx,
+                        time,
+                        self.timeline_time_at(start_x, time),
+                        self.timeline_time_at(mx, time),
+                        Color::new(1.0, 1.0, 1.0, 0.2),
+                    )?;
+                }
+            }
+            Some(TimelineDrag::Scrub(_)) | None => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use imgui::{ChildWindow, Condition, Drag, Selectable, Slider, Ui, Window};
+use libosu::{
+    hitsounds::SampleSet,
+    timing::{InheritedTimingInfo, Millis, TimingPoint, TimingPointKind, UninheritedTimingInfo},
+};
+
+use crate::history::{Change, Command};
+use crate::timestamp::format_time;
+
+use super::{ui::UiAction, Game};
+
+const SAMPLE_SETS: &[&str] = &["Normal", "Soft", "Drum"];
+
+const UNINHERITED_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+const INHERITED_COLOR: [f32; 4] = [0.3, 1.0, 0.3, 1.0];
+
+#[derive(Debug, Default)]
+pub struct TimingPanelState {
+    pub opened: bool,
+    selected: Option<usize>,
+    /// Adding or moving a timing point changes where it ends up in the list, so it's selected
+    /// by its time (and whether it's uninherited) once the change has gone through.
+    pending_selection: Option<(i32, bool)>,
+    form: TimingPointForm,
+    /// Sizes of the undo and redo stacks when the form was last loaded, so it can be reloaded
+    /// when something else changes the timing points.
+    history_mark: (usize, usize),
+}
+
+/// The values of the selected timing point while they're being edited. They're written back to
+/// the beatmap once a field is done being edited.
+#[derive(Debug, Default)]
+struct TimingPointForm {
+    offset: i32,
+    bpm: f64,
+    meter: i32,
+    slider_velocity: f64,
+    sample_set: usize,
+    sample_index: i32,
+    volume: i32,
+    kiai: bool,
+}
+
+impl TimingPointForm {
+    fn load(timing_point: &TimingPoint) -> Self {
+        let mut form = TimingPointForm {
+            offset: timing_point.time.0,
+            sample_set: match timing_point.sample_set {
+                SampleSet::Soft => 1,
+                SampleSet::Drum => 2,
+                _ => 0,
+            },
+            sample_index: timing_point.sample_index as i32,
+            volume: timing_point.volume as i32,
+            kiai: timing_point.kiai,
+            ..TimingPointForm::default()
+        };
+
+        match &timing_point.kind {
+            TimingPointKind::Uninherited(info) => {
+                form.bpm = 60_000.0 / info.mpb;
+                form.meter = info.meter as i32;
+            }
+            TimingPointKind::Inherited(info) => form.slider_velocity = info.slider_velocity,
+        }
+        form
+    }
+
+    /// The timing point with the values from the form.
+    fn apply(&self, timing_point: &TimingPoint) -> TimingPoint {
+        let mut timing_point = timing_point.clone();
+        timing_point.time = Millis(self.offset);
+        timing_point.sample_set = match self.sample_set {
+            1 => SampleSet::Soft,
+            2 => SampleSet::Drum,
+            _ => SampleSet::Normal,
+        };
+        timing_point.sample_index = self.sample_index.max(0) as _;
+        timing_point.volume = self.volume.max(0).min(100) as _;
+        timing_point.kiai = self.kiai;
+
+        match &mut timing_point.kind {
+            TimingPointKind::Uninherited(info) => {
+                info.mpb = 60_000.0 / self.bpm.max(1.0);
+                info.meter = self.meter.max(1) as _;
+            }
+            TimingPointKind::Inherited(info) => {
+                info.slider_velocity = self.slider_velocity.max(0.1).min(10.0);
+            }
+        }
+        timing_point
+    }
+}
+
+fn is_uninherited(timing_point: &TimingPoint) -> bool {
+    matches!(timing_point.kind, TimingPointKind::Uninherited(_))
+}
+
+fn describe(timing_point: &TimingPoint) -> String {
+    let details = match &timing_point.kind {
+        TimingPointKind::Uninherited(info) => {
+            format!("{:.3} BPM {}/4", 60_000.0 / info.mpb, info.meter)
+        }
+        TimingPointKind::Inherited(info) => format!("{:.2}x", info.slider_velocity),
+    };
+    let kiai = if timing_point.kiai { " kiai" } else { "" };
+    format!("{}  {}{}", format_time(timing_point.time), details, kiai)
+}
+
+/// Timing points are kept sorted by time, with uninherited ones before inherited ones at the same
+/// time like in osu!.
+fn sort_timing_points(timing_points: &mut Vec<TimingPoint>) {
+    timing_points.sort_by_key(|tp| (tp.time.0, !is_uninherited(tp)));
+}
+
+pub(super) fn draw_timing_panel(
+    ui: &Ui,
+    state: &mut TimingPanelState,
+    timing_points: &[TimingPoint],
+    current_time: Millis,
+    history_mark: (usize, usize),
+    actions: &mut Vec<UiAction>,
+) {
+    if !state.opened {
+        return;
+    }
+
+    if let Some((time, uninherited)) = state.pending_selection.take() {
+        state.selected = timing_points
+            .iter()
+            .position(|tp| tp.time.0 == time && is_uninherited(tp) == uninherited);
+        if let Some(idx) = state.selected {
+            state.form = TimingPointForm::load(&timing_points[idx]);
+        }
+    }
+    if state
+        .selected
+        .map_or(false, |idx| idx >= timing_points.len())
+    {
+        state.selected = None;
+    }
+
+    // reload the form whenever the timing points might have changed, for example after undoing
+    if state.history_mark != history_mark {
+        state.history_mark = history_mark;
+        if let Some(idx) = state.selected {
+            state.form = TimingPointForm::load(&timing_points[idx]);
+        }
+    }
+
+    let mut opened = true;
+    Window::new("Timing Setup")
+        .opened(&mut opened)
+        .size([360.0, 420.0], Condition::FirstUseEver)
+        .build(ui, || {
+            if ui.button("Add Red Line") {
+                actions.push(UiAction::AddTimingPoint {
+                    time: current_time,
+                    uninherited: true,
+                });
+                state.pending_selection = Some((current_time.0, true));
+            }
+            ui.same_line();
+            if ui.button("Add Green Line") {
+                actions.push(UiAction::AddTimingPoint {
+                    time: current_time,
+                    uninherited: false,
+                });
+                state.pending_selection = Some((current_time.0, false));
+            }
+
+            ChildWindow::new("timing_points")
+                .size([0.0, 180.0])
+                .border(true)
+                .build(ui, || {
+                    for (idx, timing_point) in timing_points.iter().enumerate() {
+                        let color = if is_uninherited(timing_point) {
+                            UNINHERITED_COLOR
+                        } else {
+                            INHERITED_COLOR
+                        };
+                        ui.text_colored(color, "|");
+                        ui.same_line();
+
+                        let label = format!("{}##timing{}", describe(timing_point), idx);
+                        if Selectable::new(&label)
+                            .selected(state.selected == Some(idx))
+                            .build(ui)
+                        {
+                            state.selected = Some(idx);
+                            state.form = TimingPointForm::load(timing_point);
+                        }
+                    }
+                });
+
+            let idx = match state.selected {
+                Some(idx) => idx,
+                None => {
+                    ui.text_disabled("Select a timing point to edit it.");
+                    return;
+                }
+            };
+            let timing_point = &timing_points[idx];
+
+            let form = &mut state.form;
+            let mut commit = false;
+            ui.input_int("Offset (ms)", &mut form.offset).build();
+            commit |= ui.is_item_deactivated_after_edit();
+            if is_uninherited(timing_point) {
+                Drag::new("BPM")
+                    .range(1.0, 1000.0)
+                    .speed(0.1)
+                    .display_format("%.3f")
+                    .build(ui, &mut form.bpm);
+                commit |= ui.is_item_deactivated_after_edit();
+                ui.input_int("Meter", &mut form.meter).build();
+                commit |= ui.is_item_deactivated_after_edit();
+            } else {
+                Drag::new("Slider Velocity")
+                    .range(0.1, 10.0)
+                    .speed(0.01)
+                    .display_format("%.2fx")
+                    .build(ui, &mut form.slider_velocity);
+                commit |= ui.is_item_deactivated_after_edit();
+            }
+            commit |= ui.combo_simple_string("Sample Set", &mut form.sample_set, SAMPLE_SETS);
+            ui.input_int("Custom Index", &mut form.sample_index).build();
+            commit |= ui.is_item_deactivated_after_edit();
+            Slider::new("Volume", 0, 100).build(ui, &mut form.volume);
+            commit |= ui.is_item_deactivated_after_edit();
+            commit |= ui.checkbox("Kiai Time", &mut form.kiai);
+
+            let mut updated = None;
+            if commit {
+                updated = Some(form.apply(timing_point));
+            }
+            if ui.button("Move to Current Time") {
+                let mut moved = timing_point.clone();
+                moved.time = current_time;
+                updated = Some(moved);
+            }
+            ui.same_line();
+            if ui.button("Delete") {
+                actions.push(UiAction::DeleteTimingPoint(idx));
+                state.selected = None;
+            }
+
+            if let Some(updated) = updated {
+                state.pending_selection = Some((updated.time.0, is_uninherited(&updated)));
+                actions.push(UiAction::UpdateTimingPoint(idx, updated));
+            }
+        });
+    state.opened = opened;
+}
+
+impl Game {
+    /// Changes the timing points as one step that can be undone.
+    pub(super) fn edit_timing_points(&mut self, name: &str, f: impl FnOnce(&mut Vec<TimingPoint>)) {
+        let before = self.beatmap.inner.timing_points.clone();
+        let mut after = before.clone();
+        f(&mut after);
+        sort_timing_points(&mut after);
+
+        let mut command = Command::new(name);
+        command.push(Change::TimingPoints { before, after });
+        self.execute(command);
+    }
+
+    /// Adds a timing point that continues whatever was in effect at that time.
+    pub(super) fn add_timing_point(&mut self, time: Millis, uninherited: bool) {
+        if self.warn_if_duplicate(time, uninherited, None) {
+            return;
+        }
+
+        let seconds = time.as_seconds();
+        let kind = if uninherited {
+            let (mpb, meter) = match self.beatmap.uninherited_timing_point_at(seconds) {
+                Some(TimingPoint {
+                    kind: TimingPointKind::Uninherited(info),
+                    ..
+                }) => (info.mpb, info.meter),
+                _ => (500.0, 4),
+            };
+            TimingPointKind::Uninherited(UninheritedTimingInfo { mpb, meter })
+        } else {
+            let slider_velocity = match self.beatmap.inherited_timing_point_at(seconds) {
+                Some(TimingPoint {
+                    kind: TimingPointKind::Inherited(info),
+                    ..
+                }) => info.slider_velocity,
+                _ => 1.0,
+            };
+            TimingPointKind::Inherited(InheritedTimingInfo { slider_velocity })
+        };
+
+        let timing_point = match self.beatmap.timing_point_at(seconds) {
+            Some(current) => TimingPoint {
+                kind,
+                time,
+                ..current.clone()
+            },
+            None => TimingPoint {
+                kind,
+                time,
+                sample_set: SampleSet::Normal,
+                sample_index: 0,
+                volume: 100,
+                kiai: false,
+            },
+        };
+
+        let name = if uninherited {
+            "Add red line"
+        } else {
+            "Add green line"
+        };
+        self.edit_timing_points(name, |timing_points| timing_points.push(timing_point));
+    }
+
+    pub(super) fn update_timing_point(&mut self, idx: usize, timing_point: TimingPoint) {
+        if idx >= self.beatmap.inner.timing_points.len() {
+            return;
+        }
+        if self.warn_if_duplicate(timing_point.time, is_uninherited(&timing_point), Some(idx)) {
+            return;
+        }
+
+        self.edit_timing_points("Edit timing point", |timing_points| {
+            timing_points[idx] = timing_point;
+        });
+    }
+
+    /// Whether there's already a line of the same kind at some time, other than the one at
+    /// `except`. Two of them would fight over which one is in effect, so this warns about it.
+    fn warn_if_duplicate(&self, time: Millis, uninherited: bool, except: Option<usize>) -> bool {
+        let duplicate = self
+            .beatmap
+            .inner
+            .timing_points
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| Some(*idx) != except)
+            .any(|(_, tp)| tp.time.0 == time.0 && is_uninherited(tp) == uninherited);
+        if duplicate {
+            warn!(
+                "there is already a {} line at {}ms",
+                if uninherited { "red" } else { "green" },
+                time.0
+            );
+        }
+        duplicate
+    }
+
+    pub(super) fn delete_timing_point(&mut self, idx: usize) {
+        if idx >= self.beatmap.inner.timing_points.len() {
+            return;
+        }
+
+        self.edit_timing_points("Delete timing point", |timing_points| {
+            timing_points.remove(idx);
+        });
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::Result;
+use ggez::{
+    graphics::{Color, DrawParam},
+    Context,
+};
+use imgui::{Drag, Ui, Window};
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    math::Point,
+};
+
+use crate::history::{Change, Command};
+
+use super::select::{object_points, osu_to_screen};
+use super::{ui::UiAction, Game, PLAYFIELD_BOUNDS};
+
+const PIVOTS: &[&str] = &["Playfield Center", "Selection Center", "Custom Point"];
+
+/// What the selection is transformed around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pivot {
+    PlayfieldCenter,
+    SelectionCenter,
+    /// A point in osu!pixels.
+    Point(i32, i32),
+}
+
+/// A flip, rotation and scale around a pivot, applied in that order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Clockwise, in degrees.
+    pub rotation: f64,
+    pub scale: f64,
+    pub pivot: Pivot,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            flip_horizontal: false,
+            flip_vertical: false,
+            rotation: 0.0,
+            scale: 1.0,
+            pivot: Pivot::SelectionCenter,
+        }
+    }
+}
+
+impl Transform {
+    pub fn flip_horizontal() -> Self {
+        Transform {
+            flip_horizontal: true,
+            ..Transform::default()
+        }
+    }
+
+    pub fn flip_vertical() -> Self {
+        Transform {
+            flip_vertical: true,
+            ..Transform::default()
+        }
+    }
+
+    fn apply(&self, point: Point<i32>, pivot: (f64, f64)) -> (f64, f64) {
+        let mut x = point.x as f64 - pivot.0;
+        let mut y = point.y as f64 - pivot.1;
+        if self.flip_horizontal {
+            x = -x;
+        }
+        if self.flip_vertical {
+            y = -y;
+        }
+
+        // y points down, so this turns clockwise on screen
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+        (pivot.0 + x * self.scale, pivot.1 + y * self.scale)
+    }
+}
+
+#[derive(Debug)]
+pub struct TransformDialogState {
+    pub opened: bool,
+    transform: Transform,
+    /// Index into `PIVOTS`.
+    pivot: usize,
+    point: [i32; 2],
+}
+
+impl Default for TransformDialogState {
+    fn default() -> Self {
+        TransformDialogState {
+            opened: false,
+            transform: Transform::default(),
+            pivot: 1,
+            point: [256, 192],
+        }
+    }
+}
+
+pub(super) fn draw_transform_dialog(
+    ui: &Ui,
+    state: &mut TransformDialogState,
+    has_selection: bool,
+    fits: bool,
+    actions: &mut Vec<UiAction>,
+) {
+    if !state.opened {
+        actions.push(UiAction::PreviewTransform(None));
+        return;
+    }
+    let mut opened = true;
+    Window::new("Transform Selection")
+        .opened(&mut opened)
+        .always_auto_resize(true)
+        .build(ui, || {
+            let transform = &mut state.transform;
+            ui.checkbox("Flip Horizontally", &mut transform.flip_horizontal);
+            ui.checkbox("Flip Vertically", &mut transform.flip_vertical);
+            Drag::new("Rotation")
+                .range(-180.0, 180.0)
+                .speed(0.5)
+                .display_format("%.1f deg")
+                .build(ui, &mut transform.rotation);
+            Drag::new("Scale")
+                .range(0.1, 4.0)
+                .speed(0.01)
+                .display_format("%.2fx")
+                .build(ui, &mut transform.scale);
+
+            ui.combo_simple_string("Pivot", &mut state.pivot, PIVOTS);
+            if state.pivot == 2 {
+                ui.input_int2("Point", &mut state.point).build();
+            }
+            transform.pivot = match state.pivot {
+                0 => Pivot::PlayfieldCenter,
+                2 => Pivot::Point(state.point[0], state.point[1]),
+                _ => Pivot::SelectionCenter,
+            };
+
+            if !has_selection {
+                ui.text_disabled("Select some objects to transform them.");
+            } else if !fits {
+                ui.text_colored(
+                    [1.0, 0.6, 0.2, 1.0],
+                    "The selection doesn't fit in the playfield.",
+                );
+            }
+
+            if ui.button("Apply") && has_selection && fits {
+                actions.push(UiAction::ApplyTransform(*transform));
+                *transform = Transform {
+                    pivot: transform.pivot,
+                    ..Transform::default()
+                };
+            }
+            ui.same_line();
+            if ui.button("Reset") {
+                *transform = Transform {
+                    pivot: transform.pivot,
+                    ..Transform::default()
+                };
+            }
+        });
+    state.opened = opened;
+
+    let preview = if state.opened && has_selection {
+        Some(state.transform)
+    } else {
+        None
+    };
+    actions.push(UiAction::PreviewTransform(preview));
+}
+
+impl Game {
+    fn transform_pivot(&self, pivot: Pivot) -> (f64, f64) {
+        match pivot {
+            Pivot::PlayfieldCenter => (256.0, 192.0),
+            Pivot::Point(x, y) => (x as f64, y as f64),
+            Pivot::SelectionCenter => {
+                let points = self
+                    .selected_objects
+                    .iter()
+                    .map(|idx| &self.beatmap.hit_objects[*idx].inner)
+                    .filter(|ho| !ho.kind.is_spinner())
+                    .flat_map(object_points)
+                    .collect::<Vec<_>>();
+                if points.is_empty() {
+                    return (256.0, 192.0);
+                }
+                let min_x = points.iter().map(|p| p.x).min().unwrap();
+                let max_x = points.iter().map(|p| p.x).max().unwrap();
+                let min_y = points.iter().map(|p| p.y).min().unwrap();
+                let max_y = points.iter().map(|p| p.y).max().unwrap();
+                ((min_x + max_x) as f64 / 2.0, (min_y + max_y) as f64 / 2.0)
+            }
+        }
+    }
+
+    /// The selected objects after the transform, and whether they all fit in the playfield.
+    /// Spinners are left alone since they're always in the middle.
+    fn transformed_selection(&self, transform: &Transform) -> (Vec<(usize, HitObject)>, bool) {
+        let pivot = self.transform_pivot(transform.pivot);
+        let round = |(x, y): (f64, f64)| Point::new(x.round() as i32, y.round() as i32);
+
+        let mut fits = true;
+        let mut objects = Vec::new();
+        for idx in self.selected_objects.iter() {
+            let mut ho = self.beatmap.hit_objects[*idx].inner.clone();
+            if ho.kind.is_spinner() {
+                continue;
+            }
+
+            ho.pos = round(transform.apply(ho.pos, pivot));
+            if let HitObjectKind::Slider(info) = &mut ho.kind {
+                for point in info.control_points.iter_mut() {
+                    *point = round(transform.apply(*point, pivot));
+                }
+                // the shape is scaled, so the length is too, but it still has to end on a tick
+                info.pixel_length =
+                    self.snap_slider_length(ho.start_time, info.pixel_length * transform.scale);
+            }
+
+            fits &= object_points(&ho)
+                .iter()
+                .all(|p| p.x >= 0 && p.x <= 512 && p.y >= 0 && p.y <= 384);
+            objects.push((*idx, ho));
+        }
+        (objects, fits)
+    }
+
+    pub(super) fn transform_fits(&self) -> bool {
+        match &self.transform_preview {
+            Some(transform) => self.transformed_selection(transform).1,
+            None => true,
+        }
+    }
+
+    pub(super) fn set_transform_preview(&mut self, transform: Option<Transform>) {
+        self.transform_preview = transform;
+    }
+
+    pub(super) fn apply_transform(&mut self, transform: Transform) {
+        let (objects, fits) = self.transformed_selection(&transform);
+        if !fits {
+            warn!("not transforming the selection since it wouldn't fit in the playfield");
+            return;
+        }
+
+        let mut command = Command::new("Transform");
+        for (idx, after) in objects {
+            command.push(Change::ModifyHitObject {
+                index: idx,
+                before: self.beatmap.hit_objects[idx].inner.clone(),
+                after,
+            });
+        }
+        self.execute(command);
+    }
+
+    /// Draws where the selected objects would end up with the transform that's being set up.
+    pub(super) fn draw_transform_preview(&self, ctx: &mut Context) -> Result<()> {
+        let transform = match &self.transform_preview {
+            Some(transform) => transform,
+            None => return Ok(()),
+        };
+
+        let (objects, fits) = self.transformed_selection(transform);
+        let cs_scale = PLAYFIELD_BOUNDS.w / 640.0;
+        let cs_real = self.beatmap.inner.difficulty.circle_size_osupx() * cs_scale;
+        let color = if fits {
+            Color::new(1.0, 1.0, 1.0, 0.5)
+        } else {
+            Color::new(1.0, 0.4, 0.2, 0.5)
+        };
+        for (_, ho) in objects.iter() {
+            if ho.kind.is_slider() {
+                Game::render_slider_wireframe(ctx, &object_points(ho), PLAYFIELD_BOUNDS, color)?;
+            }
+
+            let pos = osu_to_screen(ho.pos.x as f32, ho.pos.y as f32);
+            self.skin.hitcircleselect.draw(
+                ctx,
+                (cs_real * 2.0, cs_real * 2.0),
+                DrawParam::default().dest(pos).color(color),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,51 +1,309 @@
 use anyhow::Result;
 use ggez::Context;
-use imgui::{Condition, MenuItem, Slider, TabBar, TabItem, Window};
+use imgui::{Drag, MenuItem};
+use libosu::{
+    hitobject::SliderSplineKind,
+    hitsounds::{Additions, SampleSet},
+    timing::{Millis, TimingPoint},
+};
 
-use super::Game;
+use crate::audio::TempoEstimate;
+use crate::beatmap::BeatmapProperties;
+use crate::timestamp::Timestamp;
+
+use super::bpm_detect::{draw_bpm_detection, BpmDetectionState};
+use super::combo::MAX_COLOR_SKIP;
+use super::distance_snap::DISTANCE_SPACING_RANGE;
+use super::go_to::{draw_go_to_timestamp, GoToState};
+use super::hitsound_edit::{draw_hitsound_panel, HitsoundPanelState, ADDITIONS};
+use super::slider_edit::SLIDER_KINDS;
+use super::song_setup::{draw_song_setup, ColorPreview, SongSetupState};
+use super::spectrogram::{draw_spectrogram_panel, SpectrogramSettings, SpectrogramState};
+use super::timing::{draw_timing_panel, TimingPanelState};
+use super::transform::{draw_transform_dialog, Transform, TransformDialogState};
+use super::{Game, BEAT_DIVISORS, PLAYBACK_RATES};
+
+/// Something the user asked for through the UI, which is handled after the frame is drawn
+/// since the UI can't borrow the rest of the game while it's being built.
+#[derive(Debug)]
+pub enum UiAction {
+    Save,
+    Revert,
+    /// Undo this many steps.
+    Undo(usize),
+    /// Redo this many steps.
+    Redo(usize),
+    Cut,
+    Copy,
+    Paste,
+    CopyTimestamp,
+    GoToTimestamp(Timestamp),
+    /// Show the spectrogram with these settings, or None to hide it.
+    SetSpectrogram(Option<SpectrogramSettings>),
+    DetectTempo,
+    AcceptTempo(TempoEstimate),
+    SetBeatDivisor(u32),
+    SetPlaybackRate(f64),
+    AddTimingPoint {
+        time: Millis,
+        uninherited: bool,
+    },
+    UpdateTimingPoint(usize, TimingPoint),
+    DeleteTimingPoint(usize),
+    ApplySongSetup(Box<BeatmapProperties>),
+    /// Show the colors being edited in Song Setup, or None to go back to the beatmap's.
+    PreviewColors(Option<ColorPreview>),
+    ToggleNewCombo,
+    SetColorSkip(u32),
+    SetHitsoundEdge(Option<usize>),
+    ToggleAddition(Additions),
+    SetSampleSet(SampleSet),
+    SetAdditionSet(SampleSet),
+    SetCustomIndex(i32),
+    SetSampleVolume(i32),
+    SetSliderKind(SliderSplineKind),
+    SetDistanceSnap(bool),
+    SetDistanceSpacing(f64),
+    /// Show where the selection would end up, or None to stop showing it.
+    PreviewTransform(Option<Transform>),
+    ApplyTransform(Transform),
+}
 
 #[derive(Debug, Default)]
 pub struct UiState {
-    song_setup_opened: bool,
-    song_setup_artist: String,
-    song_setup_romanized_artist: String,
-    song_setup_title: String,
-    song_setup_romanized_title: String,
-    song_setup_mapper: String,
-    song_setup_source: String,
-    song_setup_tags: String,
-    song_setup_hp: f64,
+    song_setup: SongSetupState,
+    hitsound_panel: HitsoundPanelState,
+    timing_panel: TimingPanelState,
+    transform_dialog: TransformDialogState,
+    go_to: GoToState,
+    spectrogram: SpectrogramState,
+    bpm_detection: BpmDetectionState,
 }
 
 impl Game {
     pub(super) fn draw_ui(&mut self, ctx: &mut Context, state: &mut UiState) -> Result<()> {
+        let mut actions = Vec::new();
+        self.prepare_song_setup(&mut state.song_setup);
+        let beat_divisor = self.beat_divisor();
+        let playback_rate = self.playback_rate;
+        let has_selection = !self.selected_objects.is_empty();
+        let color_skip = self.selection_color_skip();
+        let hitsound_summary = self.hitsound_summary();
+        let slider_kind = self.selection_slider_kind();
+        let distance_snap = self.distance_snap;
+        let transform_fits = self.transform_fits();
+        let tempo_section = self.tempo_section();
+        let tempo_status = self.tempo_status();
+        let mut distance_spacing = self.beatmap.inner.distance_spacing as f64;
+        // what the BPM would be at normal speed
+        let base_bpm = self.effective_bpm().map(|bpm| bpm / playback_rate);
+        let timing_points = self.beatmap.inner.timing_points.clone();
+        let current_time = match &self.song {
+            Some(song) => Millis::from_seconds(song.position()?),
+            None => Millis(0),
+        };
+        let spectrogram_view = self.spectrogram_view(current_time.as_seconds());
+        let history_mark = (
+            self.history.undo_stack().len(),
+            self.history.redo_stack().len(),
+        );
+
+        // most recent first
+        let undo_names = self
+            .history
+            .undo_stack()
+            .iter()
+            .rev()
+            .map(|command| command.name.clone())
+            .collect::<Vec<_>>();
+        let redo_names = self
+            .history
+            .redo_stack()
+            .iter()
+            .rev()
+            .map(|command| command.name.clone())
+            .collect::<Vec<_>>();
+
         self.imgui.render(ctx, 1.0, |ui| {
             // menu bar
             if let Some(menu_bar) = ui.begin_main_menu_bar() {
                 if let Some(menu) = ui.begin_menu("File") {
-                    MenuItem::new("Save <C-s>").build(ui);
+                    if MenuItem::new("Save <C-s>").build(ui) {
+                        actions.push(UiAction::Save);
+                    }
                     MenuItem::new("Create Difficulty").build(ui);
                     ui.separator();
-                    MenuItem::new("Song Setup").build_with_ref(ui, &mut state.song_setup_opened);
-                    MenuItem::new("Revert to Saved <C-l>").build(ui);
+                    MenuItem::new("Song Setup").build_with_ref(ui, &mut state.song_setup.opened);
+                    if MenuItem::new("Revert to Saved <C-l>").build(ui) {
+                        actions.push(UiAction::Revert);
+                    }
                     ui.separator();
                     MenuItem::new("Open Song Folder").build(ui);
                     MenuItem::new("Exit <Esc>").build(ui);
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("Edit") {
+                    if MenuItem::new("Undo <C-z>")
+                        .enabled(!undo_names.is_empty())
+                        .build(ui)
+                    {
+                        actions.push(UiAction::Undo(1));
+                    }
+                    if MenuItem::new("Redo <C-y>")
+                        .enabled(!redo_names.is_empty())
+                        .build(ui)
+                    {
+                        actions.push(UiAction::Redo(1));
+                    }
+                    ui.separator();
+                    if MenuItem::new("Cut <C-x>").enabled(has_selection).build(ui) {
+                        actions.push(UiAction::Cut);
+                    }
+                    if MenuItem::new("Copy <C-c>").enabled(has_selection).build(ui) {
+                        actions.push(UiAction::Copy);
+                    }
+                    if MenuItem::new("Paste <C-v>").build(ui) {
+                        actions.push(UiAction::Paste);
+                    }
+                    if MenuItem::new("Copy Timestamp <C-S-c>")
+                        .enabled(has_selection)
+                        .build(ui)
+                    {
+                        actions.push(UiAction::CopyTimestamp);
+                    }
+                    MenuItem::new("Go to Timestamp...").build_with_ref(ui, &mut state.go_to.opened);
+
+                    // clicking on an entry in the history goes back (or forward) to right after
+                    // that step was done
+                    if !undo_names.is_empty() || !redo_names.is_empty() {
+                        ui.separator();
+                    }
+                    for (i, name) in redo_names.iter().enumerate().rev() {
+                        let label = format!("{}##redo{}", name, i);
+                        if MenuItem::new(&label).selected(false).build(ui) {
+                            actions.push(UiAction::Redo(i + 1));
+                        }
+                    }
+                    for (i, name) in undo_names.iter().enumerate() {
+                        let label = format!("{}##undo{}", name, i);
+                        if MenuItem::new(&label).selected(i == 0).build(ui) {
+                            actions.push(UiAction::Undo(i));
+                        }
+                    }
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("View") {
+                    MenuItem::new("Spectrogram").build_with_ref(ui, &mut state.spectrogram.opened);
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("Compose") {
+                    if MenuItem::new("Distance Snap")
+                        .selected(distance_snap)
+                        .build(ui)
+                    {
+                        actions.push(UiAction::SetDistanceSnap(!distance_snap));
+                    }
+                    let (min_spacing, max_spacing) = DISTANCE_SPACING_RANGE;
+                    if Drag::new("Distance Spacing")
+                        .range(min_spacing, max_spacing)
+                        .speed(0.01)
+                        .display_format("%.2fx")
+                        .build(ui, &mut distance_spacing)
+                    {
+                        actions.push(UiAction::SetDistanceSpacing(distance_spacing));
+                    }
+                    ui.separator();
+                    if MenuItem::new("Toggle New Combo <Q>")
+                        .enabled(has_selection)
+                        .build(ui)
+                    {
+                        actions.push(UiAction::ToggleNewCombo);
+                    }
+                    if let Some(menu) = ui.begin_menu_with_enabled("Color Skip", has_selection) {
+                        for skip in 0..=MAX_COLOR_SKIP {
+                            if MenuItem::new(&skip.to_string())
+                                .selected(color_skip == Some(skip))
+                                .build(ui)
+                            {
+                                actions.push(UiAction::SetColorSkip(skip));
+                            }
+                        }
+                        menu.end();
+                    }
+                    if let Some(menu) = ui.begin_menu_with_enabled("Slider Type", has_selection) {
+                        for (kind, name) in SLIDER_KINDS.iter() {
+                            if MenuItem::new(name)
+                                .selected(slider_kind == Some(*kind))
+                                .build(ui)
+                            {
+                                actions.push(UiAction::SetSliderKind(*kind));
+                            }
+                        }
+                        menu.end();
+                    }
+                    ui.separator();
+                    MenuItem::new("Hitsounds Panel")
+                        .build_with_ref(ui, &mut state.hitsound_panel.opened);
+                    for (addition, name, key) in ADDITIONS.iter() {
+                        if MenuItem::new(&format!("Toggle {} <{}>", name, key))
+                            .enabled(has_selection)
+                            .build(ui)
+                        {
+                            actions.push(UiAction::ToggleAddition(*addition));
+                        }
+                    }
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("Design") {
+                    MenuItem::new("Transform...")
+                        .build_with_ref(ui, &mut state.transform_dialog.opened);
+                    ui.separator();
+                    if MenuItem::new("Flip Horizontally")
+                        .enabled(has_selection)
+                        .build(ui)
+                    {
+                        actions.push(UiAction::ApplyTransform(Transform::flip_horizontal()));
+                    }
+                    if MenuItem::new("Flip Vertically")
+                        .enabled(has_selection)
+                        .build(ui)
+                    {
+                        actions.push(UiAction::ApplyTransform(Transform::flip_vertical()));
+                    }
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("Timing") {
+                    MenuItem::new("Timing Setup Panel")
+                        .build_with_ref(ui, &mut state.timing_panel.opened);
+                    MenuItem::new("Detect BPM...")
+                        .build_with_ref(ui, &mut state.bpm_detection.opened);
+                    ui.separator();
+                    if let Some(menu) = ui.begin_menu("Beat Snap Divisor") {
+                        for divisor in BEAT_DIVISORS.iter() {
+                            if MenuItem::new(&format!("1/{}", divisor))
+                                .selected(*divisor == beat_divisor)
+                                .build(ui)
+                            {
+                                actions.push(UiAction::SetBeatDivisor(*divisor));
+                            }
+                        }
+                        menu.end();
+                    }
+                    if let Some(menu) = ui.begin_menu("Playback Rate") {
+                        for rate in PLAYBACK_RATES.iter() {
+                            let label = match base_bpm {
+                                Some(bpm) => format!("{}% ({:.2} BPM)", rate * 100.0, bpm * rate),
+                                None => format!("{}%", rate * 100.0),
+                            };
+                            if MenuItem::new(&label)
+                                .selected((*rate - playback_rate).abs() < f64::EPSILON)
+                                .build(ui)
+                            {
+                                actions.push(UiAction::SetPlaybackRate(*rate));
+                            }
+                        }
+                        menu.end();
+                    }
                     menu.end();
                 }
                 if let Some(menu) = ui.begin_menu("Web") {
@@ -57,57 +315,112 @@ impl Game {
                 menu_bar.end();
             }
 
-            if state.song_setup_opened {
-                Window::new("Song Setup")
-                    .opened(&mut false)
-                    .collapsible(false)
-                    .always_auto_resize(true)
-                    .size([180.0, 240.0], Condition::Appearing)
-                    .build(&ui, || {
-                        TabBar::new("song_setup").build(&ui, || {
-                            TabItem::new("General").build(&ui, || {
-                                ui.group(|| {
-                                    ui.input_text("Artist", &mut state.song_setup_artist)
-                                        .build();
-                                    ui.input_text(
-                                        "Romanized Artist",
-                                        &mut state.song_setup_romanized_artist,
-                                    )
-                                    .build();
-                                    ui.input_text("Title", &mut state.song_setup_title).build();
-                                    ui.input_text(
-                                        "Romanized Title",
-                                        &mut state.song_setup_romanized_title,
-                                    )
-                                    .build();
-                                    ui.input_text("Mapper", &mut state.song_setup_mapper)
-                                        .build();
-                                    ui.input_text("Source", &mut state.song_setup_source)
-                                        .build();
-                                    ui.input_text("Tags", &mut state.song_setup_tags).build();
-                                });
-                            });
-                            TabItem::new("Difficulty").build(&ui, || {
-                                Slider::new("HP Drain Rate", 0.0, 10.0)
-                                    .display_format("%.1f")
-                                    .build(ui, &mut state.song_setup_hp);
-                                Slider::new("Circle Size", 0.0, 10.0)
-                                    .display_format("%.1f")
-                                    .build(ui, &mut state.song_setup_hp);
-                            });
-                            TabItem::new("Audio").build(&ui, || {});
-                            TabItem::new("Colors").build(&ui, || {});
-                            TabItem::new("Design").build(&ui, || {});
-                            TabItem::new("Advanced").build(&ui, || {});
-                        });
+            draw_timing_panel(
+                ui,
+                &mut state.timing_panel,
+                &timing_points,
+                current_time,
+                history_mark,
+                &mut actions,
+            );
 
-                        ui.button("OK");
-                        ui.same_line();
-                        ui.button("Cancel");
-                    });
-            }
+            draw_song_setup(ui, &mut state.song_setup, &mut actions);
+            draw_hitsound_panel(
+                ui,
+                &mut state.hitsound_panel,
+                hitsound_summary.as_ref(),
+                &mut actions,
+            );
+            draw_go_to_timestamp(ui, &mut state.go_to, &mut actions);
+            draw_spectrogram_panel(ui, &mut state.spectrogram, &spectrogram_view, &mut actions);
+            draw_bpm_detection(
+                ui,
+                &mut state.bpm_detection,
+                tempo_section,
+                &tempo_status,
+                &mut actions,
+            );
+            draw_transform_dialog(
+                ui,
+                &mut state.transform_dialog,
+                has_selection,
+                transform_fits,
+                &mut actions,
+            );
         });
 
+        for action in actions {
+            self.handle_ui_action(ctx, action)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn handle_ui_action(&mut self, ctx: &mut Context, action: UiAction) -> Result<()> {
+        match action {
+            UiAction::Save => {
+                if let Err(err) = self.save_beatmap() {
+                    error!("failed to save beatmap: {}", err);
+                }
+            }
+            UiAction::Revert => {
+                if let Err(err) = self.revert_beatmap(ctx) {
+                    error!("failed to revert beatmap: {}", err);
+                }
+            }
+            UiAction::Cut => self.cut_selection(),
+            UiAction::Copy => {
+                self.copy_selection();
+            }
+            UiAction::Paste => self.paste(),
+            UiAction::CopyTimestamp => self.copy_timestamp(),
+            UiAction::SetSpectrogram(settings) => self.set_spectrogram_settings(settings),
+            UiAction::DetectTempo => self.detect_tempo(),
+            UiAction::AcceptTempo(estimate) => self.accept_tempo(estimate),
+            UiAction::GoToTimestamp(timestamp) => {
+                if let Err(err) = self.go_to_timestamp(&timestamp) {
+                    error!("failed to go to {}: {}", timestamp, err);
+                }
+            }
+            UiAction::SetBeatDivisor(divisor) => self.set_beat_divisor(divisor),
+            UiAction::AddTimingPoint { time, uninherited } => {
+                self.add_timing_point(time, uninherited)
+            }
+            UiAction::UpdateTimingPoint(idx, timing_point) => {
+                self.update_timing_point(idx, timing_point)
+            }
+            UiAction::DeleteTimingPoint(idx) => self.delete_timing_point(idx),
+            UiAction::ApplySongSetup(properties) => self.apply_song_setup(*properties),
+            UiAction::PreviewColors(preview) => self.set_color_preview(preview),
+            UiAction::ToggleNewCombo => self.toggle_new_combo(),
+            UiAction::SetColorSkip(skip) => self.set_color_skip(skip),
+            UiAction::SetHitsoundEdge(edge) => self.set_hitsound_edge(edge),
+            UiAction::ToggleAddition(addition) => self.toggle_addition(addition),
+            UiAction::SetSampleSet(sample_set) => self.set_sample_set(sample_set),
+            UiAction::SetAdditionSet(addition_set) => self.set_addition_set(addition_set),
+            UiAction::SetCustomIndex(custom_index) => self.set_custom_index(custom_index),
+            UiAction::SetSampleVolume(volume) => self.set_sample_volume(volume),
+            UiAction::SetSliderKind(kind) => self.set_slider_kind(kind),
+            UiAction::SetDistanceSnap(enabled) => self.set_distance_snap(enabled),
+            UiAction::SetDistanceSpacing(spacing) => self.set_distance_spacing(spacing),
+            UiAction::PreviewTransform(transform) => self.set_transform_preview(transform),
+            UiAction::ApplyTransform(transform) => self.apply_transform(transform),
+            UiAction::SetPlaybackRate(rate) => {
+                if let Err(err) = self.set_playback_rate(rate) {
+                    error!("failed to change playback rate: {}", err);
+                }
+            }
+            UiAction::Undo(steps) => {
+                for _ in 0..steps {
+                    self.undo();
+                }
+            }
+            UiAction::Redo(steps) => {
+                for _ in 0..steps {
+                    self.redo();
+                }
+            }
+        }
         Ok(())
     }
 }
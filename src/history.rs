@@ -0,0 +1,310 @@
+use libosu::{hitobject::HitObject, timing::TimingPoint};
+
+use crate::beatmap::{BeatmapExt, BeatmapProperties};
+use crate::hitobject::HitObjectExt;
+
+/// A single reversible change to the beatmap.
+///
+/// Indices always refer to the state of the hit object list at the moment the change is applied,
+/// so the changes in a command must be applied in order (and reverted in reverse order).
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Inserts a hit object so that it ends up at the given index.
+    InsertHitObject { index: usize, object: HitObject },
+
+    /// Removes the hit object at the given index.
+    RemoveHitObject { index: usize, object: HitObject },
+
+    /// Replaces the hit object at the given index.
+    ModifyHitObject {
+        index: usize,
+        before: HitObject,
+        after: HitObject,
+    },
+
+    /// Replaces all of the timing points.
+    TimingPoints {
+        before: Vec<TimingPoint>,
+        after: Vec<TimingPoint>,
+    },
+
+    /// Changes the metadata, difficulty settings and so on.
+    Properties {
+        before: Box<BeatmapProperties>,
+        after: Box<BeatmapProperties>,
+    },
+}
+
+impl Change {
+    fn apply(&self, beatmap: &mut BeatmapExt) {
+        match self {
+            Change::InsertHitObject { index, object } => {
+                beatmap
+                    .hit_objects
+                    .insert(*index, HitObjectExt::new(object.clone()));
+            }
+            Change::RemoveHitObject { index, .. } => {
+                beatmap.hit_objects.remove(*index);
+            }
+            Change::ModifyHitObject { index, after, .. } => {
+                beatmap.hit_objects[*index].inner = after.clone();
+            }
+            Change::TimingPoints { after, .. } => {
+                beatmap.inner.timing_points = after.clone();
+            }
+            Change::Properties { after, .. } => after.apply_to(beatmap),
+        }
+    }
+
+    fn inverse(&self) -> Change {
+        match self {
+            Change::InsertHitObject { index, object } => Change::RemoveHitObject {
+                index: *index,
+                object: object.clone(),
+            },
+            Change::RemoveHitObject { index, object } => Change::InsertHitObject {
+                index: *index,
+                object: object.clone(),
+            },
+            Change::ModifyHitObject {
+                index,
+                before,
+                after,
+            } => Change::ModifyHitObject {
+                index: *index,
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Change::TimingPoints { before, after } => Change::TimingPoints {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Change::Properties { before, after } => Change::Properties {
+                before: after.clone(),
+                after: before.clone(),
+            },
+        }
+    }
+
+    /// Whether this change adds or removes hit objects, which shifts the indices of the hit
+    /// objects after it.
+    pub fn reorders_hit_objects(&self) -> bool {
+        matches!(
+            self,
+            Change::InsertHitObject { .. } | Change::RemoveHitObject { .. }
+        )
+    }
+
+    /// Whether this change touches the hit objects, meaning stacking and combo colors need to be
+    /// recomputed afterwards. Timing and difficulty changes count too, since they change how long
+    /// sliders are and how objects stack.
+    pub fn affects_hit_objects(&self) -> bool {
+        matches!(
+            self,
+            Change::InsertHitObject { .. }
+                | Change::RemoveHitObject { .. }
+                | Change::ModifyHitObject { .. }
+                | Change::TimingPoints { .. }
+                | Change::Properties { .. }
+        )
+    }
+
+    pub fn affects_timing_points(&self) -> bool {
+        matches!(self, Change::TimingPoints { .. })
+    }
+}
+
+/// A named group of changes that is undone and redone as one step.
+#[derive(Clone, Debug)]
+pub struct Command {
+    pub name: String,
+    pub changes: Vec<Change>,
+}
+
+impl Command {
+    pub fn new(name: impl Into<String>) -> Self {
+        Command {
+            name: name.into(),
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn reorders_hit_objects(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.reorders_hit_objects())
+    }
+
+    pub fn affects_hit_objects(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.affects_hit_objects())
+    }
+
+    pub fn affects_timing_points(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.affects_timing_points())
+    }
+
+    pub fn apply(&self, beatmap: &mut BeatmapExt) {
+        for change in self.changes.iter() {
+            change.apply(beatmap);
+        }
+    }
+
+    pub fn revert(&self, beatmap: &mut BeatmapExt) {
+        for change in self.changes.iter().rev() {
+            change.inverse().apply(beatmap);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+}
+
+impl History {
+    /// Records a command that has already been applied. This throws away anything that could
+    /// have been redone.
+    pub fn push(&mut self, command: Command) {
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Commands that can be undone, oldest first.
+    pub fn undo_stack(&self) -> &[Command] {
+        &self.undo_stack
+    }
+
+    /// Commands that can be redone, the next one to be redone last.
+    pub fn redo_stack(&self) -> &[Command] {
+        &self.redo_stack
+    }
+
+    pub fn undo(&mut self, beatmap: &mut BeatmapExt) -> Option<&Command> {
+        let command = self.undo_stack.pop()?;
+        command.revert(beatmap);
+        self.redo_stack.push(command);
+        self.redo_stack.last()
+    }
+
+    pub fn redo(&mut self, beatmap: &mut BeatmapExt) -> Option<&Command> {
+        let command = self.redo_stack.pop()?;
+        command.apply(beatmap);
+        self.undo_stack.push(command);
+        self.undo_stack.last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libosu::{
+        beatmap::Beatmap,
+        hitobject::HitObjectKind,
+        hitsounds::{Additions, SampleInfo},
+        math::Point,
+        timing::Millis,
+    };
+
+    use super::*;
+
+    fn circle(time: i32) -> HitObject {
+        HitObject {
+            start_time: Millis(time),
+            pos: Point::new(256, 192),
+            kind: HitObjectKind::Circle,
+            new_combo: false,
+            skip_color: 0,
+            additions: Additions::empty(),
+            sample_info: SampleInfo::default(),
+        }
+    }
+
+    fn times(beatmap: &BeatmapExt) -> Vec<i32> {
+        beatmap
+            .hit_objects
+            .iter()
+            .map(|ho| ho.inner.start_time.0)
+            .collect()
+    }
+
+    /// Applies a command and records it, the same way the editor does.
+    fn execute(history: &mut History, beatmap: &mut BeatmapExt, command: Command) {
+        command.apply(beatmap);
+        history.push(command);
+    }
+
+    fn insert(index: usize, time: i32) -> Command {
+        let mut command = Command::new("Place circle");
+        command.push(Change::InsertHitObject {
+            index,
+            object: circle(time),
+        });
+        command
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut beatmap = BeatmapExt::new(Beatmap::default());
+        let mut history = History::default();
+
+        execute(&mut history, &mut beatmap, insert(0, 1000));
+        let mut command = Command::new("Move");
+        command.push(Change::ModifyHitObject {
+            index: 0,
+            before: circle(1000),
+            after: circle(2000),
+        });
+        command.push(Change::InsertHitObject {
+            index: 0,
+            object: circle(500),
+        });
+        execute(&mut history, &mut beatmap, command);
+        assert_eq!(times(&beatmap), vec![500, 2000]);
+
+        assert_eq!(history.undo(&mut beatmap).unwrap().name, "Move");
+        assert_eq!(times(&beatmap), vec![1000]);
+        assert_eq!(history.undo(&mut beatmap).unwrap().name, "Place circle");
+        assert!(times(&beatmap).is_empty());
+        assert!(history.undo(&mut beatmap).is_none());
+        assert_eq!(history.redo_stack().len(), 2);
+
+        assert_eq!(history.redo(&mut beatmap).unwrap().name, "Place circle");
+        assert_eq!(history.redo(&mut beatmap).unwrap().name, "Move");
+        assert_eq!(times(&beatmap), vec![500, 2000]);
+        assert!(history.redo(&mut beatmap).is_none());
+        assert_eq!(history.undo_stack().len(), 2);
+    }
+
+    #[test]
+    fn new_command_clears_redo() {
+        let mut beatmap = BeatmapExt::new(Beatmap::default());
+        let mut history = History::default();
+
+        execute(&mut history, &mut beatmap, insert(0, 1000));
+        execute(&mut history, &mut beatmap, insert(1, 2000));
+        history.undo(&mut beatmap);
+        assert_eq!(history.redo_stack().len(), 1);
+
+        execute(&mut history, &mut beatmap, insert(1, 3000));
+        assert!(history.redo_stack().is_empty());
+        assert!(history.redo(&mut beatmap).is_none());
+        assert_eq!(times(&beatmap), vec![1000, 3000]);
+    }
+}
@@ -1,4 +1,7 @@
-use libosu::hitobject::HitObject;
+use libosu::{
+    hitobject::{HitObject, HitObjectKind},
+    math::Point,
+};
 
 #[derive(Debug)]
 pub struct HitObjectExt {
@@ -17,4 +20,15 @@ impl HitObjectExt {
             color_idx: 0,
         }
     }
+
+    /// Where the object ends. Unlike [`HitObject::end_pos`], which is the far end of a slider,
+    /// this takes repeats into account, so a slider that goes back and forth ends at its head.
+    pub fn final_pos(&self) -> Point<f64> {
+        match &self.inner.kind {
+            HitObjectKind::Slider(info) if info.num_repeats % 2 == 0 => {
+                self.inner.pos.to_float().unwrap()
+            }
+            _ => self.inner.end_pos(),
+        }
+    }
 }
@@ -10,9 +10,11 @@ extern crate bass_sys as bass;
 mod audio;
 mod beatmap;
 mod game;
+mod history;
 mod hitobject;
 mod imgui_wrapper;
 mod skin;
+mod timestamp;
 mod utils;
 
 use std::path::PathBuf;
@@ -26,11 +28,13 @@ use imgui_wrapper::ImGuiWrapper;
 use structopt::StructOpt;
 
 use crate::game::Game;
+use crate::timestamp::Timestamp;
 
 #[derive(StructOpt)]
 struct Opt {
+    /// Where to start, either in seconds or as an editor timestamp like "01:23:456 (1,2) -"
     #[structopt(short = "s")]
-    start_time: Option<f64>,
+    start_time: Option<Timestamp>,
 
     path: Option<PathBuf>,
 
@@ -85,7 +89,7 @@ fn main() -> Result<()> {
     }
 
     if let Some(start_time) = opt.start_time {
-        game.jump_to_time(start_time)?;
+        game.go_to_timestamp(&start_time)?;
     }
 
     event::run(ctx, event_loop, game)
@@ -38,8 +38,11 @@ create_skin! {
     [approachcircle, "approachcircle", false],
     [hitcircle, "hitcircle", false],
     [hitcircleoverlay, "hitcircleoverlay", false],
+    [hitcircleselect, "hitcircleselect", false],
     [reversearrow, "reversearrow", false],
     [sliderb, "sliderb", true],
+    [spinnerapproachcircle, "spinner-approachcircle", false],
+    [spinnercircle, "spinner-circle", false],
 
     // TODO: actually read numbers from skin.ini
     [default0, "default-0", false],
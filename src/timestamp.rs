@@ -0,0 +1,184 @@
+//! Editor timestamps, the `01:23:456 (1,2,3) - ` strings that modders use to point at objects.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Error, Result};
+use libosu::timing::Millis;
+
+/// A time in the map, and optionally the combo numbers of the objects starting there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timestamp {
+    pub time: Millis,
+    pub combo_numbers: Vec<usize>,
+}
+
+/// Formats a time the same way the osu! editor does, like 01:23:456.
+pub fn format_time(time: Millis) -> String {
+    let sign = if time.0 < 0 { "-" } else { "" };
+    let millis = time.0.abs();
+    format!(
+        "{}{:02}:{:02}:{:03}",
+        sign,
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn parse_time(time: &str) -> Result<Millis> {
+    let (sign, time) = match time.strip_prefix('-') {
+        Some(time) => (-1, time),
+        None => (1, time),
+    };
+
+    let parts = time
+        .split(':')
+        .map(|part| part.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| anyhow!("invalid time: {:?}", time))?;
+    let millis = match parts.as_slice() {
+        [minutes, seconds, millis] => minutes * 60_000 + seconds * 1000 + millis,
+        [minutes, seconds] => minutes * 60_000 + seconds * 1000,
+        _ => bail!("invalid time: {:?}", time),
+    };
+    Ok(Millis(sign * millis))
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    /// Parses either an editor timestamp or a plain number of seconds.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Ok(seconds) = s.parse::<f64>() {
+            return Ok(Timestamp {
+                time: Millis::from_seconds(seconds),
+                combo_numbers: Vec::new(),
+            });
+        }
+
+        // anything after the dash is a comment about the objects
+        let s = s.split(" -").next().unwrap_or("").trim();
+        let (time, objects) = match s.find('(') {
+            Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+            None => (s, None),
+        };
+
+        let combo_numbers = match objects {
+            Some(objects) => {
+                let end = objects
+                    .find(')')
+                    .ok_or_else(|| anyhow!("unclosed object list"))?;
+                objects[..end]
+                    .split(',')
+                    .map(|number| {
+                        number
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| anyhow!("invalid combo number: {:?}", number))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Timestamp {
+            time: parse_time(time.trim())?,
+            combo_numbers,
+        })
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_time(self.time))?;
+        if !self.combo_numbers.is_empty() {
+            let numbers = self
+                .combo_numbers
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>();
+            write!(f, " ({})", numbers.join(","))?;
+        }
+        write!(f, " - ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Timestamp {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn plain_seconds() {
+        assert_eq!(parse("83.5").time, Millis(83_500));
+        assert!(parse("83.5").combo_numbers.is_empty());
+    }
+
+    #[test]
+    fn minutes_seconds_millis() {
+        assert_eq!(
+            parse("01:23:456 (1,2,3) - "),
+            Timestamp {
+                time: Millis(83_456),
+                combo_numbers: vec![1, 2, 3],
+            }
+        );
+        assert_eq!(parse("01:23:456").time, Millis(83_456));
+    }
+
+    #[test]
+    fn minutes_seconds() {
+        assert_eq!(parse("01:23").time, Millis(83_000));
+    }
+
+    #[test]
+    fn negative_time() {
+        assert_eq!(parse("-00:01:500 - ").time, Millis(-1500));
+        assert_eq!(format_time(Millis(-1500)), "-00:01:500");
+    }
+
+    #[test]
+    fn trailing_comment() {
+        let timestamp = parse("01:23:456 (1,2) - move this a bit - or not");
+        assert_eq!(timestamp.time, Millis(83_456));
+        assert_eq!(timestamp.combo_numbers, vec![1, 2]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("01:23:456 (1,2".parse::<Timestamp>().is_err());
+        assert!("01:23:456 (1,x)".parse::<Timestamp>().is_err());
+        assert!("1:2:3:4".parse::<Timestamp>().is_err());
+        assert!("".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for timestamp in &[
+            Timestamp {
+                time: Millis(83_456),
+                combo_numbers: vec![1, 2, 3],
+            },
+            Timestamp {
+                time: Millis(-1500),
+                combo_numbers: Vec::new(),
+            },
+        ] {
+            let text = timestamp.to_string();
+            assert_eq!(&parse(&text), timestamp, "{:?}", text);
+        }
+        assert_eq!(
+            Timestamp {
+                time: Millis(83_456),
+                combo_numbers: vec![1, 2],
+            }
+            .to_string(),
+            "01:23:456 (1,2) - "
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
@@ -26,3 +28,30 @@ pub fn fuck_you_windows(
 pub fn rect_contains(rect: &Rect, x: f32, y: f32) -> bool {
     x >= rect.x && x <= rect.x + rect.w && y >= rect.y && y <= rect.y + rect.h
 }
+
+/// Writes a file by writing to a temporary file next to it and renaming it over the target.
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => bail!("not a file path: {:?}", path),
+    };
+
+    let tmp_path = parent.join(format!(".{}.tmp", file_name));
+    let result = (|| -> Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}